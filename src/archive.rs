@@ -0,0 +1,399 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::clone::Backend;
+use crate::config;
+use crate::git;
+use crate::meta::{self, SpaceMeta};
+use crate::paths;
+use crate::ui;
+
+const INFO_FILE: &str = "archive";
+const BUNDLE_FILE: &str = "branches.bundle";
+const STAGED_PATCH: &str = "staged.patch";
+const UNSTAGED_PATCH: &str = "unstaged.patch";
+const UNTRACKED_PATCH: &str = "untracked.patch";
+/// Scratch namespace for bundle refs, so restoring into a worktree space
+/// never rewrites branches of the shared mirror wholesale.
+const RESTORE_REFS: &str = "refs/spaces-restore";
+
+/// A space saved by `spaces archive`: its metadata plus what the mirror
+/// cannot give back (unpushed commits and local changes).
+pub struct Archive {
+    pub path: PathBuf,
+    pub meta: SpaceMeta,
+    pub head_branch: Option<String>,
+    pub head: String,
+    pub backend: Backend,
+    pub branches: Vec<String>,
+    pub archived_at: u64,
+    /// Cone-mode sparse checkout directories; empty for full checkouts.
+    pub sparse: Vec<String>,
+    /// Commits a shallow clone held, restored as `--depth`.
+    pub depth: Option<u32>,
+    pub filter: Option<String>,
+}
+
+impl Archive {
+    pub fn has_changes(&self) -> bool {
+        [STAGED_PATCH, UNSTAGED_PATCH, UNTRACKED_PATCH]
+            .iter()
+            .any(|file| self.path.join(file).exists())
+    }
+}
+
+pub fn archive_dir(repo_root: &Path, clones_dir: &Path) -> Result<PathBuf> {
+    let configured = config::cfg_default("spaces.archive.dir", "SPACES_ARCHIVE_DIR", "", None, repo_root)?;
+    if configured.is_empty() {
+        return Ok(clones_dir.join(format!("{}archive", paths::INTERNAL_DIR_PREFIX)));
+    }
+    let mut dir = paths::expand_home(&configured);
+    if dir.is_relative() {
+        dir = repo_root.join(dir);
+    }
+    Ok(dir)
+}
+
+pub fn read(entry: &Path) -> Option<Archive> {
+    let info = entry.join(INFO_FILE);
+    let meta = meta::read_file(&info)?;
+    let info_str = info.to_string_lossy().to_string();
+    let get = |key: &str| git::git_stdout_opt(["config", "-f", &info_str, "--get", key], None);
+    let get_all = |key: &str| -> Vec<String> {
+        git::git_stdout_opt(["config", "-f", &info_str, "--get-all", key], None)
+            .map(|out| out.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    };
+
+    Some(Archive {
+        path: entry.to_path_buf(),
+        meta,
+        head_branch: get("archive.headBranch"),
+        head: get("archive.head").unwrap_or_default(),
+        backend: match get("archive.backend").as_deref() {
+            Some("worktree") => Backend::Worktree,
+            _ => Backend::Clone,
+        },
+        branches: get_all("archive.branch"),
+        archived_at: get("archive.archivedAt").and_then(|v| v.parse().ok()).unwrap_or(0),
+        sparse: get_all("archive.sparse"),
+        depth: get("archive.depth").and_then(|v| v.parse().ok()),
+        filter: get("archive.filter"),
+    })
+}
+
+/// Lists stored archives, most recently archived first.
+pub fn list(archive_dir: &Path) -> Result<Vec<Archive>> {
+    if !archive_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut out = Vec::new();
+    for entry in fs::read_dir(archive_dir)? {
+        let path = entry?.path();
+        if let Some(archive) = read(&path) {
+            out.push(archive);
+        }
+    }
+    out.sort_by(|a, b| b.archived_at.cmp(&a.archived_at).then_with(|| a.meta.name.cmp(&b.meta.name)));
+    Ok(out)
+}
+
+/// Finds an archive by folder or space name.
+pub fn find(archive_dir: &Path, name: &str) -> Result<Archive> {
+    let archives = list(archive_dir)?;
+    let folder = paths::sanitize_branch_name(name);
+    let found = archives
+        .into_iter()
+        .find(|a| a.meta.name == name || a.path.file_name().is_some_and(|f| f.to_string_lossy() == folder));
+    match found {
+        Some(archive) => Ok(archive),
+        None => bail!("No archive named {name} (see 'spaces archive --list')"),
+    }
+}
+
+/// Saves unpushed branches and local changes of `space` into `entry`.
+/// Ignored files are not saved.
+pub fn create(space: &Path, entry: &Path, space_meta: &SpaceMeta) -> Result<Archive> {
+    if entry.exists() {
+        bail!("Archive already exists: {}", entry.display());
+    }
+    fs::create_dir_all(entry).with_context(|| format!("create archive dir {}", entry.display()))?;
+    let result = write_entry(space, entry, space_meta);
+    if result.is_err() {
+        let _ = fs::remove_dir_all(entry);
+    }
+    result
+}
+
+fn write_entry(space: &Path, entry: &Path, space_meta: &SpaceMeta) -> Result<Archive> {
+    let backend = Backend::of_space(space);
+    let head = git::git_stdout(["rev-parse", "HEAD"], Some(space))?;
+    let head_branch = git::git_stdout_opt(["symbolic-ref", "--quiet", "--short", "HEAD"], Some(space));
+
+    // Worktree branches live in the shared mirror, so only the checked-out
+    // one belongs to the space and "pushed" means reachable from the others.
+    let (candidates, pushed): (Vec<String>, Vec<String>) = match (backend, &head_branch) {
+        (Backend::Clone, _) => (
+            git::git_stdout_opt(["for-each-ref", "--format=%(refname:short)", "refs/heads"], Some(space))
+                .map(|out| out.lines().map(str::to_string).collect())
+                .unwrap_or_default(),
+            vec!["--remotes".to_string()],
+        ),
+        (Backend::Worktree, Some(branch)) => {
+            (vec![branch.clone()], vec![format!("--exclude={branch}"), "--branches".to_string()])
+        }
+        (Backend::Worktree, None) => (Vec::new(), vec!["--branches".to_string()]),
+    };
+
+    let unpushed_count = |rev: &str| -> usize {
+        let mut args = vec!["rev-list".to_string(), "--count".to_string(), rev.to_string(), "--not".to_string()];
+        args.extend(pushed.iter().cloned());
+        git::git_stdout_opt(&args, Some(space))
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0)
+    };
+    let branches: Vec<String> = candidates.into_iter().filter(|b| unpushed_count(b) > 0).collect();
+    let detached_unpushed = head_branch.is_none() && unpushed_count("HEAD") > 0;
+
+    if !branches.is_empty() || detached_unpushed {
+        let bundle = entry.join(BUNDLE_FILE);
+        let mut args = vec!["bundle".to_string(), "create".to_string(), bundle.to_string_lossy().to_string()];
+        args.extend(branches.iter().cloned());
+        if detached_unpushed {
+            args.push("HEAD".to_string());
+        }
+        args.push("--not".to_string());
+        args.extend(pushed.iter().cloned());
+        git::git_check(&args, Some(space)).context("bundle unpushed commits")?;
+    }
+
+    // Any failure here must stop the archive: the caller removes the space
+    // once this returns.
+    let staged = git::git_stdout_raw(["diff", "--cached", "--binary"], Some(space)).context("diff staged changes")?;
+    write_patch(entry, STAGED_PATCH, staged)?;
+    let unstaged = git::git_stdout_raw(["diff", "--binary"], Some(space)).context("diff unstaged changes")?;
+    write_patch(entry, UNSTAGED_PATCH, unstaged)?;
+    let untracked = git::git_stdout_raw(["ls-files", "--others", "--exclude-standard", "-z"], Some(space))
+        .context("list untracked files")?;
+    let mut untracked_patch = Vec::new();
+    for rel in untracked.split(|b| *b == 0).filter(|s| !s.is_empty()) {
+        let rel = String::from_utf8_lossy(rel).to_string();
+        let output = git::git_output(["diff", "--no-index", "--binary", "--", "/dev/null", &rel], Some(space))?;
+        // --no-index exits with 1 when the files differ, which they always
+        // do here; anything else is an error.
+        if output.status.code() != Some(1) {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("diff untracked file {rel}: {}", stderr.trim());
+        }
+        untracked_patch.extend(output.stdout);
+    }
+    write_patch(entry, UNTRACKED_PATCH, untracked_patch)?;
+
+    let info = entry.join(INFO_FILE);
+    meta::write_file(&info, space_meta)?;
+    let info_str = info.to_string_lossy().to_string();
+    let set = |key: &str, value: &str| git::git_check(["config", "-f", &info_str, key, value], None);
+    set("archive.head", &head)?;
+    set("archive.backend", backend.as_str())?;
+    set("archive.archivedAt", &meta::now().to_string())?;
+    if let Some(branch) = &head_branch {
+        set("archive.headBranch", branch)?;
+    }
+    for branch in &branches {
+        git::git_check(["config", "-f", &info_str, "--add", "archive.branch", branch], None)?;
+    }
+
+    // Restore recreates the space with the same checkout shape.
+    if crate::clone::is_sparse(space) {
+        let dirs = git::git_stdout(["sparse-checkout", "list"], Some(space)).context("list sparse directories")?;
+        for dir in dirs.lines() {
+            git::git_check(["config", "-f", &info_str, "--add", "archive.sparse", dir], None)?;
+        }
+    }
+    if backend == Backend::Clone {
+        if git::git_stdout_opt(["rev-parse", "--is-shallow-repository"], Some(space)).as_deref() == Some("true") {
+            let depth = git::git_stdout(["rev-list", "--count", "HEAD"], Some(space))?;
+            set("archive.depth", &depth)?;
+        }
+        if let Some(filter) = git::git_stdout_opt(["config", "remote.origin.partialclonefilter"], Some(space)) {
+            set("archive.filter", &filter)?;
+        }
+    }
+
+    read(entry).context("read back archive")
+}
+
+fn write_patch(entry: &Path, name: &str, patch: Vec<u8>) -> Result<()> {
+    if patch.is_empty() {
+        return Ok(());
+    }
+    fs::write(entry.join(name), patch).with_context(|| format!("write {name}"))
+}
+
+/// Replays an archive onto a freshly created space that is detached at the
+/// archived HEAD or its base. Archived branches are only created or
+/// fast-forwarded; a branch that moved on since archiving is left alone and
+/// the archived tip stays under `refs/spaces-restore/`.
+pub fn apply(archive: &Archive, space: &Path) -> Result<()> {
+    let bundle = archive.path.join(BUNDLE_FILE);
+    let mut kept = Vec::new();
+    if bundle.exists() {
+        ui::log_step("Restoring unpushed commits...");
+        let bundle_str = bundle.to_string_lossy().to_string();
+        if git::git_check(["bundle", "verify", &bundle_str], Some(space)).is_err()
+            && git::git_stdout_opt(["rev-parse", "--is-shallow-repository"], Some(space)).as_deref() == Some("true")
+        {
+            ui::log_warn("Archived commits build on history the shallow clone lacks; fetching full history");
+            git::git_check(["fetch", "--unshallow", "origin"], Some(space)).context("unshallow restored clone")?;
+        }
+        git::git_check(["bundle", "verify", &bundle_str], Some(space)).context("verify archived bundle")?;
+        let refspec = format!("+refs/heads/*:{RESTORE_REFS}/*");
+        git::git_check(["fetch", &bundle_str, &refspec], Some(space))?;
+        if archive.head_branch.is_none() {
+            git::git_check(["fetch", &bundle_str, "HEAD"], Some(space))?;
+        }
+
+        for branch in &archive.branches {
+            let saved = format!("{RESTORE_REFS}/{branch}");
+            if fast_forwards(space, branch, &saved) {
+                git::git_check(["update-ref", &format!("refs/heads/{branch}"), &saved], Some(space))?;
+                git::git_check(["update-ref", "-d", &saved], Some(space))?;
+            } else {
+                ui::log_warn(&format!("Branch {branch} moved since it was archived; archived tip kept as {saved}"));
+                kept.push(branch.clone());
+            }
+        }
+    }
+
+    match &archive.head_branch {
+        Some(branch) if !kept.contains(branch) && fast_forwards(space, branch, &archive.head) => {
+            git::git_check(["checkout", "-B", branch, &archive.head], Some(space))?;
+            let upstream = format!("origin/{branch}");
+            if git::git_check(["rev-parse", "--verify", "--quiet", &upstream], Some(space)).is_ok() {
+                let _ = git::git_check(["branch", "--set-upstream-to", &upstream], Some(space));
+            }
+        }
+        Some(branch) => {
+            ui::log_warn(&format!("Branch {branch} moved since it was archived; restoring detached"));
+            git::git_check(["checkout", "--detach", &archive.head], Some(space))?;
+        }
+        None => git::git_check(["checkout", "--detach", &archive.head], Some(space))?,
+    }
+
+    for (name, index) in [(STAGED_PATCH, true), (UNSTAGED_PATCH, false), (UNTRACKED_PATCH, false)] {
+        let patch = archive.path.join(name);
+        if !patch.exists() {
+            continue;
+        }
+        let patch_str = patch.to_string_lossy().to_string();
+        let result = if index {
+            git::git_check(["apply", "--index", &patch_str], Some(space))
+        } else {
+            git::git_check(["apply", &patch_str], Some(space))
+        };
+        result.with_context(|| format!("apply archived {name}"))?;
+    }
+    Ok(())
+}
+
+/// Whether moving `branch` to `target` loses no commits: the branch does
+/// not exist yet or `target` contains it. Worktree spaces share branches
+/// with the mirror, where they may have moved on.
+fn fast_forwards(space: &Path, branch: &str, target: &str) -> bool {
+    let local = format!("refs/heads/{branch}");
+    if git::git_check(["rev-parse", "--verify", "--quiet", &local], Some(space)).is_err() {
+        return true;
+    }
+    git::git_check(["merge-base", "--is-ancestor", &local, target], Some(space)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{self, TempDir};
+
+    fn space_meta() -> SpaceMeta {
+        SpaceMeta {
+            id: Some(2),
+            name: "feature".to_string(),
+            folder: "feature".to_string(),
+            branch: Some("feature".to_string()),
+            base_ref: "main".to_string(),
+            created_at: 1,
+            version: "test".to_string(),
+            mirror_head: None,
+            profile: None,
+            lock: None,
+        }
+    }
+
+    /// Clones `origin` to `space` with an unpushed commit on `feature` plus
+    /// staged, unstaged and untracked changes, archives it into `entry`
+    /// and deletes the space. Returns the archived HEAD.
+    fn archive_space(tmp: &Path, entry: &Path) -> String {
+        let space = tmp.join("space");
+        testutil::git(tmp, &["clone", "-q", "origin", "space"]);
+        testutil::git(&space, &["switch", "-q", "-c", "feature"]);
+        let head = testutil::commit(&space, "unpushed");
+        fs::write(space.join("staged.txt"), "staged\n").unwrap();
+        testutil::git(&space, &["add", "staged.txt"]);
+        fs::write(space.join("file.txt"), "unstaged\n").unwrap();
+        fs::write(space.join("untracked.txt"), "untracked\n").unwrap();
+
+        let archive = create(&space, entry, &space_meta()).unwrap();
+        assert_eq!(archive.branches, vec!["feature".to_string()]);
+        assert_eq!(archive.head, head);
+        assert!(archive.has_changes());
+        fs::remove_dir_all(&space).unwrap();
+        head
+    }
+
+    fn assert_changes_restored(space: &Path) {
+        assert_eq!(testutil::git(space, &["diff", "--cached", "--name-only"]), "staged.txt");
+        assert_eq!(testutil::git(space, &["diff", "--name-only"]), "file.txt");
+        assert_eq!(fs::read_to_string(space.join("file.txt")).unwrap(), "unstaged\n");
+        assert_eq!(fs::read_to_string(space.join("untracked.txt")).unwrap(), "untracked\n");
+        assert_eq!(testutil::git(space, &["ls-files", "--others", "--exclude-standard"]), "untracked.txt");
+    }
+
+    #[test]
+    fn archive_then_restore_round_trips_commits_and_changes() {
+        let tmp = TempDir::new("archive-round-trip");
+        testutil::init_repo(&tmp.path().join("origin"));
+        let entry = tmp.path().join("archive");
+        let head = archive_space(tmp.path(), &entry);
+
+        testutil::git(tmp.path(), &["clone", "-q", "origin", "restored"]);
+        let restored = tmp.path().join("restored");
+        testutil::git(&restored, &["checkout", "-q", "--detach", "origin/main"]);
+        apply(&read(&entry).unwrap(), &restored).unwrap();
+
+        assert_eq!(testutil::git(&restored, &["symbolic-ref", "--short", "HEAD"]), "feature");
+        assert_eq!(testutil::git(&restored, &["rev-parse", "HEAD"]), head);
+        assert!(testutil::git(&restored, &["for-each-ref", RESTORE_REFS]).is_empty());
+        assert_changes_restored(&restored);
+    }
+
+    #[test]
+    fn restore_keeps_a_branch_that_moved_on() {
+        let tmp = TempDir::new("archive-moved");
+        testutil::init_repo(&tmp.path().join("origin"));
+        let entry = tmp.path().join("archive");
+        let head = archive_space(tmp.path(), &entry);
+
+        testutil::git(tmp.path(), &["clone", "-q", "origin", "restored"]);
+        let restored = tmp.path().join("restored");
+        testutil::git(&restored, &["switch", "-q", "-c", "feature"]);
+        let moved = testutil::commit(&restored, "elsewhere");
+        testutil::git(&restored, &["checkout", "-q", "--detach", "origin/main"]);
+        apply(&read(&entry).unwrap(), &restored).unwrap();
+
+        assert_eq!(testutil::git(&restored, &["rev-parse", "refs/heads/feature"]), moved);
+        assert_eq!(testutil::git(&restored, &["rev-parse", &format!("{RESTORE_REFS}/feature")]), head);
+        assert_eq!(testutil::git(&restored, &["rev-parse", "HEAD"]), head);
+        assert!(git::git_stdout_opt(["symbolic-ref", "--quiet", "HEAD"], Some(&restored)).is_none());
+        assert_changes_restored(&restored);
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(name = "spaces", version, about = "Git clone runner", arg_required_else_help = true)]
@@ -13,12 +13,26 @@ pub enum Commands {
     New(NewArgs),
     #[command(about = "Remove space clone(s) by name or id")]
     Rm(RmArgs),
+    #[command(about = "Rename a space clone")]
+    Mv(MvArgs),
+    #[command(about = "Save a space's unpushed work and remove its clone")]
+    Archive(ArchiveArgs),
+    #[command(about = "Recreate an archived space")]
+    Restore(RestoreArgs),
+    #[command(about = "Lock a space against removal")]
+    Lock(LockArgs),
+    #[command(about = "Unlock a space")]
+    Unlock(TargetArg),
     #[command(about = "Print path for a space clone or main repo")]
-    Go(TargetArg),
+    Go(GoArgs),
     #[command(about = "Run a command inside a space clone")]
     Run(RunArgs),
     #[command(about = "List space clones in this repo")]
     List(ListArgs),
+    #[command(about = "Show what needs attention in each space")]
+    Status(StatusArgs),
+    #[command(about = "Show disk usage of spaces and mirrors")]
+    Du(DuArgs),
     #[command(about = "Copy files between space clones")]
     Copy(CopyArgs),
     #[command(about = "Clean empty space clones and optionally merged PR clones")]
@@ -29,6 +43,10 @@ pub enum Commands {
     Mirrors(MirrorsArgs),
     #[command(about = "Manage spaces config values")]
     Config(ConfigArgs),
+    #[command(about = "Copy mirror objects into space clone(s) and drop their alternates")]
+    Detach(DetachArgs),
+    #[command(about = "Show or fill the pool of pre-warmed clones")]
+    Pool(PoolArgs),
 }
 
 #[derive(Args)]
@@ -36,6 +54,14 @@ pub struct TargetArg {
     pub id: String,
 }
 
+#[derive(Args)]
+pub struct GoArgs {
+    pub id: String,
+
+    #[arg(long)]
+    pub group: Option<String>,
+}
+
 #[derive(Args)]
 pub struct NewArgs {
     pub name: Option<String>,
@@ -43,15 +69,51 @@ pub struct NewArgs {
     #[arg(short = 'b', long)]
     pub branch: Option<String>,
 
+    #[arg(long, conflicts_with_all = ["branch", "pr", "from_space"])]
+    pub detach: bool,
+
     #[arg(long)]
     pub from: Option<String>,
 
+    #[arg(long, conflicts_with = "from")]
+    pub pr: Option<u32>,
+
+    #[arg(long, conflicts_with_all = ["from", "pr"])]
+    pub from_space: Option<String>,
+
+    #[arg(long, num_args = 1..)]
+    pub sparse: Vec<String>,
+
+    #[arg(long)]
+    pub depth: Option<u32>,
+
+    #[arg(long)]
+    pub shallow_since: Option<String>,
+
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    #[arg(long, conflicts_with_all = ["detach", "pr", "from_space", "sparse", "profile"])]
+    pub group: Option<String>,
+
     #[arg(long)]
     pub no_fetch: bool,
 
     #[arg(long)]
     pub no_copy: bool,
 
+    #[arg(long)]
+    pub no_pool: bool,
+
+    #[arg(long)]
+    pub no_submodules: bool,
+
+    #[arg(long)]
+    pub keep_on_failure: bool,
+
     #[arg(long)]
     pub yes: bool,
 }
@@ -64,13 +126,47 @@ pub struct MirrorsArgs {
 
 #[derive(Subcommand)]
 pub enum MirrorsCommand {
-    Update,
+    Update {
+        #[arg(long)]
+        lfs: bool,
+    },
+}
+
+#[derive(Args)]
+pub struct PoolArgs {
+    #[command(subcommand)]
+    pub command: Option<PoolCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum PoolCommand {
+    #[command(about = "Prepare clones until the pool holds --size entries")]
+    Fill {
+        #[arg(long, default_value_t = 1)]
+        size: usize,
+
+        #[arg(long)]
+        background: bool,
+    },
+    #[command(about = "Remove all pooled clones")]
+    Clear,
+}
+
+#[derive(Args)]
+pub struct DetachArgs {
+    pub targets: Vec<String>,
+
+    #[arg(long)]
+    pub all: bool,
 }
 
 #[derive(Args)]
 pub struct RmArgs {
     pub targets: Vec<String>,
 
+    #[arg(long)]
+    pub group: Option<String>,
+
     #[arg(long)]
     pub force: bool,
 
@@ -78,18 +174,128 @@ pub struct RmArgs {
     pub yes: bool,
 }
 
+#[derive(Args)]
+pub struct MvArgs {
+    pub from: String,
+
+    pub to: String,
+
+    #[arg(long)]
+    pub rename_branch: bool,
+
+    #[arg(long, value_name = "NAME")]
+    pub branch_name: Option<String>,
+
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Args)]
+pub struct ArchiveArgs {
+    #[arg(conflicts_with = "list")]
+    pub target: Option<String>,
+
+    #[arg(long)]
+    pub list: bool,
+
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Args)]
+pub struct RestoreArgs {
+    pub name: String,
+
+    #[arg(long)]
+    pub no_fetch: bool,
+
+    #[arg(long)]
+    pub no_copy: bool,
+
+    #[arg(long)]
+    pub keep_on_failure: bool,
+}
+
+#[derive(Args)]
+pub struct LockArgs {
+    pub target: String,
+
+    #[arg(long)]
+    pub reason: Option<String>,
+}
+
 #[derive(Args)]
 pub struct RunArgs {
     pub id: String,
 
+    #[arg(long)]
+    pub group: Option<String>,
+
     #[arg(trailing_var_arg = true)]
     pub cmd: Vec<String>,
 }
 
 #[derive(Args)]
 pub struct ListArgs {
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "v1")]
+    pub porcelain: Option<PorcelainFormat>,
+
+    #[arg(long, conflicts_with = "porcelain")]
+    pub json: bool,
+
     #[arg(long)]
-    pub porcelain: bool,
+    pub no_status: bool,
+
+    #[arg(long, conflicts_with_all = ["clean", "no_status"])]
+    pub dirty: bool,
+
+    #[arg(long, conflicts_with = "no_status")]
+    pub clean: bool,
+
+    #[arg(long, value_name = "GLOB")]
+    pub branch: Option<String>,
+
+    #[arg(long, conflicts_with = "no_status")]
+    pub merged: bool,
+
+    #[arg(long, value_name = "DURATION")]
+    pub older_than: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = ListSort::Name)]
+    pub sort: ListSort,
+}
+
+/// `v1` is the original path, name, branch and status; `v2` adds ids,
+/// locks and sync state.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PorcelainFormat {
+    V1,
+    V2,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListSort {
+    Name,
+    Age,
+    LastUsed,
+    Size,
+}
+
+#[derive(Args)]
+pub struct StatusArgs {
+    pub targets: Vec<String>,
+
+    #[arg(long)]
+    pub attention: bool,
+}
+
+#[derive(Args)]
+pub struct DuArgs {
+    #[arg(long)]
+    pub ignored: bool,
+
+    #[arg(long, default_value_t = 5)]
+    pub top: usize,
 }
 
 #[derive(Args)]
@@ -119,6 +325,9 @@ pub struct CleanArgs {
 
     #[arg(long)]
     pub dry_run: bool,
+
+    #[arg(long)]
+    pub force: bool,
 }
 
 #[derive(Args)]
@@ -3,13 +3,81 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
 
+use crate::config;
+use crate::copy;
 use crate::git;
+use crate::paths;
 use crate::ui;
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Backend {
+    Clone,
+    Worktree,
+}
+
+impl Backend {
+    pub fn from_config(repo_root: &Path) -> Result<Backend> {
+        let value = config::cfg_default("spaces.backend", "SPACES_BACKEND", "clone", None, repo_root)?;
+        match value.as_str() {
+            "clone" => Ok(Backend::Clone),
+            "worktree" => Ok(Backend::Worktree),
+            other => bail!("Unknown spaces.backend: {other} (expected clone or worktree)"),
+        }
+    }
+
+    pub fn of_space(path: &Path) -> Backend {
+        if path.join(".git").is_file() {
+            Backend::Worktree
+        } else {
+            Backend::Clone
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Backend::Clone => "clone",
+            Backend::Worktree => "worktree",
+        }
+    }
+}
+
 pub struct ClonePlan {
     pub path: PathBuf,
+    pub backend: Backend,
     pub branch: Option<String>,
     pub base_ref: String,
+    pub pull_request: Option<u32>,
+    pub from_space: Option<PathBuf>,
+    pub detach: bool,
+    pub sparse: Vec<String>,
+    pub depth: Option<u32>,
+    pub shallow_since: Option<String>,
+    pub filter: Option<String>,
+    pub lfs_storage: Option<PathBuf>,
+    pub dissociate: bool,
+}
+
+impl ClonePlan {
+    fn history_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(depth) = self.depth {
+            args.push(format!("--depth={depth}"));
+        }
+        if let Some(since) = &self.shallow_since {
+            args.push(format!("--shallow-since={since}"));
+        }
+        if self.depth.is_some() || self.shallow_since.is_some() {
+            // --depth implies --single-branch, which would hide the branch
+            // and base refs checked out below.
+            args.push("--no-single-branch".to_string());
+        }
+        match &self.filter {
+            Some(filter) => args.push(format!("--filter={filter}")),
+            None if !self.sparse.is_empty() => args.push("--filter=blob:none".to_string()),
+            None => {}
+        }
+        args
+    }
 }
 
 pub fn create_clone(repo_root: &Path, mirror_dir: &Path, plan: &ClonePlan) -> Result<()> {
@@ -21,24 +89,203 @@ pub fn create_clone(repo_root: &Path, mirror_dir: &Path, plan: &ClonePlan) -> Re
         fs::create_dir_all(parent).with_context(|| format!("create clones dir {parent:?}"))?;
     }
 
-    let clone_source = git::git_stdout_opt(["remote", "get-url", "origin"], Some(repo_root))
+    if plan.backend == Backend::Worktree {
+        if plan.depth.is_some() || plan.shallow_since.is_some() || plan.filter.is_some() {
+            ui::log_warn("--depth, --shallow-since and --filter are ignored by the worktree backend");
+        }
+        return create_worktree(mirror_dir, plan);
+    }
+
+    let mut clone_source = git::git_stdout_opt(["remote", "get-url", "origin"], Some(repo_root))
         .unwrap_or_else(|| repo_root.to_string_lossy().to_string());
+    let history = plan.history_args();
+    if !history.is_empty() && Path::new(&clone_source).exists() {
+        // git ignores --depth and --filter for plain local paths.
+        let absolute = fs::canonicalize(&clone_source).unwrap_or_else(|_| PathBuf::from(&clone_source));
+        clone_source = format!("file://{}", absolute.display());
+    }
+
+    let mut args = vec![
+        "clone".to_string(),
+        "--reference-if-able".to_string(),
+        mirror_dir.to_string_lossy().to_string(),
+    ];
+    if !plan.sparse.is_empty() {
+        args.push("--sparse".to_string());
+    }
+    if plan.dissociate {
+        args.push("--dissociate".to_string());
+    }
+    if let Some(storage) = &plan.lfs_storage {
+        // Set before checkout so the smudge filter fills the shared store.
+        args.push(format!("--config=lfs.storage={}", storage.display()));
+    }
+    let base_len = args.len();
+    args.extend(history.iter().cloned());
+    args.push(clone_source.clone());
+    args.push(plan.path.to_string_lossy().to_string());
 
     ui::log_step("Cloning repository...");
+    if let Err(err) = git::git_check(&args, None) {
+        if history.is_empty() {
+            return Err(err);
+        }
+        ui::log_warn(&format!("Clone with {} failed: {err}", history.join(" ")));
+        ui::log_warn("Retrying with full history");
+        if plan.path.exists() {
+            fs::remove_dir_all(&plan.path)?;
+        }
+        args.drain(base_len..base_len + history.len());
+        git::git_check(&args, None)?;
+    }
+
+    if !plan.sparse.is_empty() {
+        apply_sparse(plan)?;
+    }
+
+    if let Some(number) = plan.pull_request {
+        let branch = plan.branch.clone().unwrap_or_else(|| format!("pr-{number}"));
+        checkout_pull_request(mirror_dir, plan, number, &branch)?;
+    } else if let (Some(source), Some(branch)) = (&plan.from_space, &plan.branch) {
+        // The source space may hold commits that were never pushed.
+        git::git_check(["fetch", source.to_string_lossy().as_ref(), "HEAD"], Some(&plan.path))?;
+        git::git_check(["checkout", "-b", branch, &plan.base_ref], Some(&plan.path))?;
+    } else if let Some(branch) = &plan.branch {
+        checkout_branch(mirror_dir, plan, branch)?;
+    } else if plan.detach {
+        checkout_detached(plan)?;
+    }
+    Ok(())
+}
+
+fn checkout_detached(plan: &ClonePlan) -> Result<()> {
+    let base = resolve_base(plan)?;
+    git::git_check(["checkout", "--detach", &base], Some(&plan.path))
+}
+
+/// Brings a clone claimed from the pool up to date with the mirror and
+/// switches it to the planned branch.
+pub fn refresh_claimed(mirror_dir: &Path, plan: &ClonePlan) -> Result<()> {
+    ui::log_step("Refreshing pooled clone...");
     git::git_check(
         [
-            "clone",
-            "--reference-if-able",
+            "fetch",
+            "--prune",
             mirror_dir.to_string_lossy().as_ref(),
-            clone_source.as_str(),
-            plan.path.to_string_lossy().as_ref(),
+            "+refs/heads/*:refs/remotes/origin/*",
+            "+refs/tags/*:refs/tags/*",
         ],
-        None,
+        Some(&plan.path),
     )?;
 
     if let Some(branch) = &plan.branch {
         checkout_branch(mirror_dir, plan, branch)?;
+    } else if plan.detach {
+        checkout_detached(plan)?;
+    } else if git::git_check(["merge", "--ff-only", "--quiet", "@{upstream}"], Some(&plan.path)).is_err() {
+        ui::log_warn("Could not fast-forward pooled clone to its upstream");
+    }
+    Ok(())
+}
+
+/// The mirror is bare and keeps branches under `refs/heads`, so a base such
+/// as `origin/main` names `refs/heads/main` there.
+fn mirror_base(mirror_dir: &Path, base_ref: &str) -> String {
+    let exists = |name: &str| git::git_check(["rev-parse", "--verify", "--quiet", name], Some(mirror_dir)).is_ok();
+    match base_ref.strip_prefix("origin/") {
+        Some(branch) if !exists(&format!("refs/remotes/{base_ref}")) && exists(&format!("refs/heads/{branch}")) => {
+            format!("refs/heads/{branch}")
+        }
+        _ => base_ref.to_string(),
+    }
+}
+
+fn create_worktree(mirror_dir: &Path, plan: &ClonePlan) -> Result<()> {
+    let path = plan.path.to_string_lossy().to_string();
+    let base_ref = mirror_base(mirror_dir, &plan.base_ref);
+    let mut args = vec!["worktree".to_string(), "add".to_string()];
+    if !plan.sparse.is_empty() {
+        args.push("--no-checkout".to_string());
+    }
+
+    if let Some(number) = plan.pull_request {
+        let branch = plan.branch.clone().unwrap_or_else(|| format!("pr-{number}"));
+        args.extend(["-b".to_string(), branch, path, format!("refs/pull/{number}/head")]);
+    } else if let (Some(source), Some(branch)) = (&plan.from_space, &plan.branch) {
+        git::git_check(["fetch", source.to_string_lossy().as_ref(), "HEAD"], Some(mirror_dir))?;
+        args.extend(["-b".to_string(), branch.clone(), path, base_ref.clone()]);
+    } else if let Some(branch) = &plan.branch {
+        let local_ref = format!("refs/heads/{branch}");
+        if git::git_check(["show-ref", "--verify", "--quiet", &local_ref], Some(mirror_dir)).is_ok() {
+            args.extend([path, branch.clone()]);
+        } else {
+            args.extend(["-b".to_string(), branch.clone(), path, base_ref.clone()]);
+        }
+    } else {
+        // A branch can only be checked out by one worktree, so spaces
+        // without a branch start detached at the base ref.
+        args.extend(["--detach".to_string(), path, base_ref.clone()]);
+    }
+
+    ui::log_step("Adding worktree...");
+    git::git_check(&args, Some(mirror_dir))?;
+
+    if !plan.sparse.is_empty() {
+        apply_sparse(plan)?;
+        git::git_check(["read-tree", "-mu", "HEAD"], Some(&plan.path))?;
+    }
+    Ok(())
+}
+
+fn apply_sparse(plan: &ClonePlan) -> Result<()> {
+    ui::log_step(&format!("Sparse checkout: {}", plan.sparse.join(", ")));
+    let mut args = vec!["sparse-checkout", "set", "--cone"];
+    args.extend(plan.sparse.iter().map(|dir| dir.as_str()));
+    git::git_check(args, Some(&plan.path))
+}
+
+pub fn is_sparse(path: &Path) -> bool {
+    git::git_stdout_opt(["config", "--bool", "core.sparseCheckout"], Some(path)).as_deref() == Some("true")
+}
+
+/// Removes a worktree space through its owning repository so the mirror
+/// does not keep stale worktree entries around. Without `force`, git refuses
+/// to drop modified or untracked files.
+pub fn remove_worktree(path: &Path, force: bool) -> Result<()> {
+    let common = git::git_stdout(["rev-parse", "--path-format=absolute", "--git-common-dir"], Some(path))?;
+    let common = PathBuf::from(common);
+    let path_str = path.to_string_lossy().to_string();
+    if force {
+        git::git_check(["worktree", "remove", "--force", &path_str], Some(&common))?;
+    } else {
+        git::git_check(["worktree", "remove", &path_str], Some(&common))
+            .context("Worktree has local changes; use --force to remove it anyway")?;
+    }
+    let _ = git::git_check(["worktree", "prune"], Some(&common));
+    Ok(())
+}
+
+/// Moves a space to a new folder. Worktrees are moved through git so the
+/// mirror keeps tracking them.
+pub fn move_space(from: &Path, to: &Path) -> Result<()> {
+    if Backend::of_space(from) == Backend::Worktree {
+        let common = git::git_stdout(["rev-parse", "--path-format=absolute", "--git-common-dir"], Some(from))?;
+        git::git_check(
+            ["worktree", "move", from.to_string_lossy().as_ref(), to.to_string_lossy().as_ref()],
+            Some(Path::new(&common)),
+        )?;
+        return Ok(());
     }
+    fs::rename(from, to).with_context(|| format!("move {} to {}", from.display(), to.display()))
+}
+
+fn checkout_pull_request(mirror_dir: &Path, plan: &ClonePlan, number: u32, branch: &str) -> Result<()> {
+    let refspec = format!("+refs/pull/{number}/head:refs/heads/{branch}");
+    git::git_check(
+        ["fetch", mirror_dir.to_string_lossy().as_ref(), refspec.as_str()],
+        Some(&plan.path),
+    )?;
+    git::git_check(["checkout", branch], Some(&plan.path))?;
     Ok(())
 }
 
@@ -60,6 +307,228 @@ fn checkout_branch(mirror_dir: &Path, plan: &ClonePlan, branch: &str) -> Result<
         return Ok(());
     }
 
-    git::git_check(["checkout", "-b", branch, &plan.base_ref], Some(&plan.path))?;
+    // The base may be a remote-tracking ref, which must not become the new
+    // branch's upstream.
+    let base = resolve_base(plan)?;
+    git::git_check(["checkout", "--no-track", "-b", branch, &base], Some(&plan.path))?;
+    Ok(())
+}
+
+/// Shallow and partial clones may not contain the base ref (for example a
+/// tag or an old commit), so fetch it on demand. A branch base resolves to
+/// its remote-tracking ref, because the local branch of a clone claimed from
+/// the pool is as old as the pool entry.
+fn resolve_base(plan: &ClonePlan) -> Result<String> {
+    let remote = format!("refs/remotes/origin/{}", plan.base_ref);
+    if git::git_check(["rev-parse", "--verify", "--quiet", &remote], Some(&plan.path)).is_ok() {
+        return Ok(format!("origin/{}", plan.base_ref));
+    }
+    let commit = format!("{}^{{commit}}", plan.base_ref);
+    if git::git_check(["rev-parse", "--verify", "--quiet", &commit], Some(&plan.path)).is_ok() {
+        return Ok(plan.base_ref.clone());
+    }
+
+    let mut args = vec!["fetch".to_string(), "origin".to_string()];
+    if let Some(depth) = plan.depth {
+        args.push(format!("--depth={depth}"));
+    }
+    args.push(plan.base_ref.clone());
+    git::git_check(&args, Some(&plan.path))
+        .with_context(|| format!("fetch base ref {}", plan.base_ref))?;
+    Ok("FETCH_HEAD".to_string())
+}
+
+/// Replays the staged, unstaged and untracked changes of `source` onto
+/// `dest` without touching the source working tree, index or refs.
+pub fn carry_changes(source: &Path, dest: &Path) -> Result<()> {
+    let staged = git::git_stdout_raw(["diff", "--cached", "--binary"], Some(source)).context("diff staged changes")?;
+    let unstaged = git::git_stdout_raw(["diff", "--binary"], Some(source)).context("diff unstaged changes")?;
+    let patch_dir = paths::git_dir(dest).with_context(|| format!("no git dir for {}", dest.display()))?;
+
+    for (patch, index) in [(staged, true), (unstaged, false)] {
+        if patch.is_empty() {
+            continue;
+        }
+        let patch_file = patch_dir.join("spaces-carry.patch");
+        fs::write(&patch_file, &patch)?;
+        let patch_str = patch_file.to_string_lossy().to_string();
+        let result = if index {
+            git::git_check(["apply", "--index", &patch_str], Some(dest))
+        } else {
+            git::git_check(["apply", &patch_str], Some(dest))
+        };
+        let _ = fs::remove_file(&patch_file);
+        result.context("apply carried changes")?;
+    }
+
+    let untracked = git::git_stdout_raw(["ls-files", "--others", "--exclude-standard", "-z"], Some(source))
+        .context("list untracked files")?;
+    let mut carried = 0;
+    for rel in untracked.split(|b| *b == 0).filter(|s| !s.is_empty()) {
+        let rel = PathBuf::from(String::from_utf8_lossy(rel).to_string());
+        let from = source.join(&rel);
+        let to = dest.join(&rel);
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file_type = fs::symlink_metadata(&from)?.file_type();
+        if file_type.is_symlink() {
+            #[cfg(unix)]
+            {
+                let link = fs::read_link(&from)?;
+                let _ = fs::remove_file(&to);
+                std::os::unix::fs::symlink(link, &to)?;
+            }
+        } else if file_type.is_dir() {
+            // git lists a nested repository as one directory entry.
+            copy::copy_dir_recursive(&from, &to, &[])
+                .with_context(|| format!("copy untracked directory {}", rel.display()))?;
+        } else {
+            fs::copy(&from, &to).with_context(|| format!("copy untracked file {}", rel.display()))?;
+        }
+        carried += 1;
+    }
+    if carried > 0 {
+        ui::log_info(&format!("Carried {carried} untracked file(s)"));
+    }
     Ok(())
 }
+
+/// Returns the object directories a space borrows from, including those of
+/// its submodules.
+pub fn alternates(path: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let Some(git_dir) = paths::git_dir(path) else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    for file in alternates_files(&git_dir) {
+        let Ok(content) = fs::read_to_string(&file) else {
+            continue;
+        };
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            out.push((file.clone(), PathBuf::from(line)));
+        }
+    }
+    out
+}
+
+fn alternates_files(git_dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let top = git_dir.join("objects/info/alternates");
+    if top.exists() {
+        files.push(top);
+    }
+    let modules = git_dir.join("modules");
+    if modules.is_dir() {
+        for entry in walkdir::WalkDir::new(&modules).into_iter().filter_map(Result::ok) {
+            if entry.file_name() == "alternates" && entry.path().ends_with("objects/info/alternates") {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    files
+}
+
+/// Copies every object a clone borrows from the mirror into its own object
+/// store and drops the alternates, so the clone survives losing the mirror.
+pub fn dissociate(path: &Path) -> Result<bool> {
+    let git_dir = paths::git_dir(path).with_context(|| format!("no git dir for {}", path.display()))?;
+    let files = alternates_files(&git_dir);
+    if files.is_empty() {
+        return Ok(false);
+    }
+    for file in files {
+        // <git dir>/objects/info/alternates -> <git dir>
+        let Some(repo_git_dir) = file.parent().and_then(Path::parent).and_then(Path::parent) else {
+            continue;
+        };
+        let git_dir_arg = format!("--git-dir={}", repo_git_dir.display());
+        git::git_check([git_dir_arg.as_str(), "repack", "-a", "-d", "-q"], None)
+            .with_context(|| format!("repack {}", repo_git_dir.display()))?;
+        fs::remove_file(&file)?;
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{self, TempDir};
+
+    fn plan(path: &Path, branch: Option<&str>) -> ClonePlan {
+        ClonePlan {
+            path: path.to_path_buf(),
+            backend: Backend::Clone,
+            branch: branch.map(str::to_string),
+            base_ref: "main".to_string(),
+            pull_request: None,
+            from_space: None,
+            detach: false,
+            sparse: Vec::new(),
+            depth: None,
+            shallow_since: None,
+            filter: None,
+            lfs_storage: None,
+            dissociate: false,
+        }
+    }
+
+    #[test]
+    fn claimed_clone_branches_from_the_current_mirror() {
+        let tmp = TempDir::new("claim");
+        let origin = tmp.path().join("origin");
+        let mirror = tmp.path().join("mirror");
+        let entry = tmp.path().join("entry");
+        testutil::init_repo(&origin);
+        testutil::git(tmp.path(), &["clone", "-q", "--mirror", "origin", "mirror"]);
+        testutil::git(tmp.path(), &["clone", "-q", "--reference", "mirror", "origin", "entry"]);
+
+        // The mirror moves on after the pool entry was filled.
+        let head = testutil::commit(&origin, "later");
+        testutil::git(&mirror, &["fetch", "-q", "origin", "+refs/heads/*:refs/heads/*"]);
+
+        refresh_claimed(&mirror, &plan(&entry, Some("feature"))).unwrap();
+        assert_eq!(testutil::git(&entry, &["rev-parse", "HEAD"]), head);
+        assert_eq!(testutil::git(&entry, &["branch", "--show-current"]), "feature");
+        let upstream = git::git_stdout_opt(["config", "branch.feature.merge"], Some(&entry));
+        assert_eq!(upstream, None);
+    }
+
+    #[test]
+    fn carry_changes_copies_nested_repositories() {
+        let tmp = TempDir::new("carry");
+        let source = tmp.path().join("source");
+        let dest = tmp.path().join("dest");
+        testutil::init_repo(&source);
+        testutil::git(tmp.path(), &["clone", "-q", "source", "dest"]);
+
+        std::fs::write(source.join("file.txt"), "staged\n").unwrap();
+        testutil::git(&source, &["add", "file.txt"]);
+        std::fs::write(source.join("loose.txt"), "loose\n").unwrap();
+        testutil::init_repo(&source.join("nested"));
+
+        carry_changes(&source, &dest).unwrap();
+        assert_eq!(std::fs::read_to_string(dest.join("file.txt")).unwrap(), "staged\n");
+        assert_eq!(testutil::git(&dest, &["diff", "--cached", "--name-only"]), "file.txt");
+        assert!(dest.join("loose.txt").is_file());
+        assert!(dest.join("nested/file.txt").is_file());
+        assert_eq!(testutil::git(&source, &["diff", "--cached", "--name-only"]), "file.txt");
+    }
+
+    #[test]
+    fn claimed_clone_without_branch_fast_forwards() {
+        let tmp = TempDir::new("claim-ff");
+        let origin = tmp.path().join("origin");
+        let mirror = tmp.path().join("mirror");
+        let entry = tmp.path().join("entry");
+        testutil::init_repo(&origin);
+        testutil::git(tmp.path(), &["clone", "-q", "--mirror", "origin", "mirror"]);
+        testutil::git(tmp.path(), &["clone", "-q", "--reference", "mirror", "origin", "entry"]);
+
+        let head = testutil::commit(&origin, "later");
+        testutil::git(&mirror, &["fetch", "-q", "origin", "+refs/heads/*:refs/heads/*"]);
+
+        refresh_claimed(&mirror, &plan(&entry, None)).unwrap();
+        assert_eq!(testutil::git(&entry, &["rev-parse", "HEAD"]), head);
+    }
+}
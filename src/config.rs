@@ -22,6 +22,10 @@ pub fn scope_flag(scope: Scope) -> &'static str {
     }
 }
 
+pub fn is_truthy(value: &str) -> bool {
+    matches!(value.trim().to_ascii_lowercase().as_str(), "true" | "yes" | "on" | "1")
+}
+
 pub fn spacesrc_path(repo_root: &Path) -> PathBuf {
     repo_root.join(".spacesrc")
 }
@@ -104,40 +108,39 @@ pub fn cfg_default(
     file_key: Option<&str>,
     repo_root: &Path,
 ) -> Result<String> {
-    if let Some(value) = git_config_get(key, Scope::Local, repo_root) {
-        if !value.is_empty() {
-            return Ok(value);
-        }
+    if let Some(value) = git_config_get(key, Scope::Local, repo_root)
+        && !value.is_empty()
+    {
+        return Ok(value);
     }
 
     if let Some(file_key) = file_key {
         let values = git_config_file_get_all(file_key, repo_root);
-        if let Some(first) = values.first() {
-            if !first.is_empty() {
-                return Ok(first.clone());
-            }
+        if let Some(first) = values.first()
+            && !first.is_empty()
+        {
+            return Ok(first.clone());
         }
     } else {
         let values = git_config_file_get_all(key, repo_root);
-        if let Some(first) = values.first() {
-            if !first.is_empty() {
-                return Ok(first.clone());
-            }
+        if let Some(first) = values.first()
+            && !first.is_empty()
+        {
+            return Ok(first.clone());
         }
     }
 
-    if let Some(value) = git_config_get(key, Scope::Auto, repo_root) {
-        if !value.is_empty() {
-            return Ok(value);
-        }
+    if let Some(value) = git_config_get(key, Scope::Auto, repo_root)
+        && !value.is_empty()
+    {
+        return Ok(value);
     }
 
-    if !env_name.is_empty() {
-        if let Ok(value) = std::env::var(env_name) {
-            if !value.is_empty() {
-                return Ok(value);
-            }
-        }
+    if !env_name.is_empty()
+        && let Ok(value) = std::env::var(env_name)
+        && !value.is_empty()
+    {
+        return Ok(value);
     }
 
     Ok(fallback.to_string())
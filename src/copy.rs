@@ -189,7 +189,7 @@ pub fn copy_directories(
     Ok(())
 }
 
-fn copy_dir_recursive(src: &Path, dst: &Path, excludes: &[Pattern]) -> Result<()> {
+pub fn copy_dir_recursive(src: &Path, dst: &Path, excludes: &[Pattern]) -> Result<()> {
     for entry in WalkDir::new(src).into_iter().filter_map(Result::ok) {
         let path = entry.path();
         let rel = match path.strip_prefix(src) {
@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::clone::{self, Backend};
+use crate::git;
+use crate::paths;
+
+/// Bytes a file occupies on disk, which for sparse or small files differs
+/// from its length.
+#[cfg(unix)]
+fn disk_size(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn disk_size(metadata: &Metadata) -> u64 {
+    metadata.len()
+}
+
+/// Reports whether another link to the same file was already counted.
+#[cfg(unix)]
+fn counted_before(seen: &mut HashSet<(u64, u64)>, metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() > 1 && !seen.insert((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn counted_before(_seen: &mut HashSet<(u64, u64)>, _metadata: &Metadata) -> bool {
+    false
+}
+
+/// Disk usage of a directory tree without following symlinks. Hard links
+/// are counted once. With `skip_git`, a top-level `.git` is left out.
+pub fn dir_size(path: &Path, skip_git: bool) -> u64 {
+    let mut seen = HashSet::new();
+    let walker = WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|entry| !(skip_git && entry.depth() == 1 && entry.file_name() == ".git"));
+    walker
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| !counted_before(&mut seen, metadata))
+        .map(|metadata| disk_size(&metadata))
+        .sum()
+}
+
+/// Object directories a space reads from without owning: alternates for
+/// clones, the mirror's object store for worktrees.
+pub fn shared_object_dirs(path: &Path) -> Vec<PathBuf> {
+    if Backend::of_space(path) == Backend::Worktree {
+        return git::git_stdout_opt(["rev-parse", "--path-format=absolute", "--git-common-dir"], Some(path))
+            .map(|common| vec![PathBuf::from(common).join("objects")])
+            .unwrap_or_default();
+    }
+    let mut dirs = Vec::new();
+    for (file, dir) in clone::alternates(path) {
+        // Relative entries are relative to the objects dir holding the file.
+        let dir = match file.parent().and_then(Path::parent) {
+            Some(objects) if dir.is_relative() => objects.join(dir),
+            _ => dir,
+        };
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// The private git dir of a space: `.git` for clones, the per-worktree dir
+/// inside the mirror for worktrees.
+pub fn git_dir_size(path: &Path) -> u64 {
+    paths::git_dir(path).map(|dir| dir_size(&dir, false)).unwrap_or(0)
+}
+
+/// Ignored files and directories of a checkout with their sizes, largest
+/// first. Git collapses fully ignored directories into one entry.
+pub fn ignored_entries(path: &Path) -> Vec<(String, u64)> {
+    let Ok(output) = git::git_output(
+        ["ls-files", "-z", "--others", "--ignored", "--exclude-standard", "--directory"],
+        Some(path),
+    ) else {
+        return Vec::new();
+    };
+    let mut entries: Vec<(String, u64)> = output
+        .stdout
+        .split(|b| *b == 0)
+        .filter(|s| !s.is_empty())
+        .map(|rel| {
+            let rel = String::from_utf8_lossy(rel).to_string();
+            let size = dir_size(&path.join(&rel), false);
+            (rel, size)
+        })
+        .collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    entries
+}
@@ -45,6 +45,21 @@ where
     }
 }
 
+/// Like `git_stdout`, but keeps stdout byte for byte, as patches need.
+pub fn git_stdout_raw<I, S>(args: I, cwd: Option<&Path>) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = git_output(args, cwd)?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(stderr.trim().to_string())
+    }
+}
+
 pub fn git_stdout_opt<I, S>(args: I, cwd: Option<&Path>) -> Option<String>
 where
     I: IntoIterator<Item = S>,
@@ -0,0 +1,175 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::{self, Scope};
+use crate::git;
+use crate::paths;
+
+const RECORD_FILE: &str = ".spaces-group";
+
+/// A set of repositories whose spaces are created and removed together,
+/// defined by `spaces.group.<name>.repo` or `group.<name>.repo` in the
+/// groups file.
+pub struct Group {
+    pub name: String,
+    pub repos: Vec<PathBuf>,
+}
+
+/// A group space on disk: one clone per member repo under a shared folder.
+pub struct GroupSpace {
+    pub group: String,
+    pub name: String,
+    pub path: PathBuf,
+    pub branch: String,
+    pub members: Vec<Member>,
+}
+
+pub struct Member {
+    pub name: String,
+    pub repo: PathBuf,
+    pub path: PathBuf,
+}
+
+/// Group definitions are usually global, so config is read relative to the
+/// current repo when there is one and the working directory otherwise.
+pub fn config_root() -> PathBuf {
+    paths::repo_root()
+        .or_else(|_| std::env::current_dir().context("no current dir"))
+        .unwrap_or_else(|_| PathBuf::from("."))
+}
+
+pub fn groups_file(root: &Path) -> Result<PathBuf> {
+    let configured = config::cfg_default("spaces.groups.file", "SPACES_GROUPS_FILE", "", None, root)?;
+    if !configured.is_empty() {
+        return Ok(paths::expand_home(&configured));
+    }
+    let base = dirs::config_dir().unwrap_or_else(|| PathBuf::from("~/.config"));
+    Ok(base.join("spaces").join("groups"))
+}
+
+pub fn groups_dir(root: &Path) -> Result<PathBuf> {
+    let configured = config::cfg_default("spaces.groups.dir", "SPACES_GROUPS_DIR", "", None, root)?;
+    if !configured.is_empty() {
+        return Ok(paths::expand_home(&configured));
+    }
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("~"));
+    Ok(home.join("spaces-groups"))
+}
+
+pub fn load(name: &str, root: &Path) -> Result<Group> {
+    let mut entries = config::cfg_get_all(&format!("spaces.group.{name}.repo"), Scope::Auto, root);
+    let file = groups_file(root)?;
+    if file.exists() {
+        let file_str = file.to_string_lossy().to_string();
+        let key = format!("group.{name}.repo");
+        if let Some(out) = git::git_stdout_opt(["config", "-f", &file_str, "--get-all", &key], None) {
+            entries.extend(out.lines().map(str::to_string));
+        }
+    }
+    if entries.is_empty() {
+        bail!("Unknown group: {name} (no spaces.group.{name}.repo keys or entries in {})", file.display());
+    }
+
+    let mut repos = Vec::new();
+    for entry in entries {
+        let dir = paths::expand_home(&entry);
+        let top = git::git_stdout(["rev-parse", "--show-toplevel"], Some(&dir))
+            .with_context(|| format!("group {name}: {entry} is not a git repository"))?;
+        let top = PathBuf::from(top);
+        if !repos.contains(&top) {
+            repos.push(top);
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    for repo in &repos {
+        if !seen.insert(member_name(repo)) {
+            bail!("Group {name} has two repositories named {}", member_name(repo));
+        }
+    }
+    Ok(Group {
+        name: name.to_string(),
+        repos,
+    })
+}
+
+pub fn member_name(repo: &Path) -> String {
+    repo.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "repo".to_string())
+}
+
+pub fn space_path(root: &Path, group: &str, space: &str) -> Result<PathBuf> {
+    Ok(groups_dir(root)?
+        .join(paths::sanitize_branch_name(group))
+        .join(paths::sanitize_branch_name(space)))
+}
+
+pub fn write_record(space: &GroupSpace) -> Result<()> {
+    let file = space.path.join(RECORD_FILE);
+    let file_str = file.to_string_lossy().to_string();
+    let set = |key: &str, value: &str| git::git_check(["config", "-f", &file_str, key, value], None);
+    set("group.name", &space.group)?;
+    set("group.space", &space.name)?;
+    set("group.branch", &space.branch)?;
+    set("group.createdAt", &crate::meta::now().to_string())?;
+    for member in &space.members {
+        set(&format!("member.{}.repo", member.name), &member.repo.to_string_lossy())?;
+    }
+    Ok(())
+}
+
+pub fn read(path: &Path) -> Option<GroupSpace> {
+    let file = path.join(RECORD_FILE);
+    if !file.exists() {
+        return None;
+    }
+    let file_str = file.to_string_lossy().to_string();
+    let get = |key: &str| git::git_stdout_opt(["config", "-f", &file_str, "--get", key], None);
+
+    let mut members = Vec::new();
+    let repos = git::git_stdout_opt(["config", "-f", &file_str, "--get-regexp", "^member\\..*\\.repo$"], None)
+        .unwrap_or_default();
+    for line in repos.lines() {
+        let Some((key, repo)) = line.split_once(' ') else {
+            continue;
+        };
+        let Some(name) = key.strip_prefix("member.").and_then(|k| k.strip_suffix(".repo")) else {
+            continue;
+        };
+        members.push(Member {
+            name: name.to_string(),
+            repo: PathBuf::from(repo),
+            path: path.join(name),
+        });
+    }
+
+    Some(GroupSpace {
+        group: get("group.name")?,
+        name: get("group.space")?,
+        path: path.to_path_buf(),
+        branch: get("group.branch").unwrap_or_default(),
+        members,
+    })
+}
+
+/// Resolves a group space by its name or folder within `group`.
+pub fn resolve(root: &Path, group: &str, space: &str) -> Result<GroupSpace> {
+    let path = space_path(root, group, space)?;
+    if let Some(found) = read(&path) {
+        return Ok(found);
+    }
+    let group_dir = groups_dir(root)?.join(paths::sanitize_branch_name(group));
+    if group_dir.is_dir() {
+        for entry in fs::read_dir(&group_dir)? {
+            if let Some(found) = read(&entry?.path())
+                && found.name == space
+            {
+                return Ok(found);
+            }
+        }
+    }
+    bail!("No space {space} in group {group}")
+}
@@ -5,11 +5,25 @@ use std::process::Command;
 use anyhow::{bail, Result};
 
 use crate::config;
+use crate::profile;
+use crate::rollback;
 use crate::ui;
 
-pub fn run_hooks(phase: &str, repo_root: &Path, cwd: &Path, envs: &HashMap<String, String>) -> Result<()> {
+/// Runs the global `spaces.hook.<phase>` hooks followed by the ones from
+/// `spaces.profile.<profile>.hook.<phase>`.
+pub fn run_hooks(
+    phase: &str,
+    profile: Option<&str>,
+    repo_root: &Path,
+    cwd: &Path,
+    envs: &HashMap<String, String>,
+) -> Result<()> {
     let key = format!("spaces.hook.{phase}");
-    let hooks = config::cfg_get_all(&key, config::Scope::Auto, repo_root);
+    let mut hooks = config::cfg_get_all(&key, config::Scope::Auto, repo_root);
+    if let Some(profile) = profile {
+        let key = profile::key(profile, &format!("hook.{phase}"));
+        hooks.extend(config::cfg_get_all(&key, config::Scope::Auto, repo_root));
+    }
     if hooks.is_empty() {
         return Ok(());
     }
@@ -21,6 +35,7 @@ pub fn run_hooks(phase: &str, repo_root: &Path, cwd: &Path, envs: &HashMap<Strin
         if hook.trim().is_empty() {
             continue;
         }
+        rollback::check_interrupted()?;
         ui::log_info(&format!("Hook {}: {}", idx + 1, hook));
         let mut cmd = Command::new("sh");
         cmd.arg("-c").arg(hook).current_dir(cwd);
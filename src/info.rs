@@ -0,0 +1,195 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+
+use crate::clone::{self, Backend};
+use crate::git;
+use crate::meta;
+use crate::paths;
+use crate::targets::{self, Changes};
+
+/// Bumped whenever a field is removed or changes meaning. Adding fields
+/// does not bump it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+pub struct ListOutput {
+    pub version: u32,
+    pub spaces: Vec<SpaceInfo>,
+}
+
+/// Everything `list --json` reports about one space (or the main repo).
+#[derive(Serialize)]
+pub struct SpaceInfo {
+    pub path: PathBuf,
+    pub name: String,
+    pub id: Option<u32>,
+    pub main: bool,
+    pub kind: Option<&'static str>,
+    pub sparse: bool,
+    pub branch: Option<String>,
+    pub status: String,
+    pub upstream: Option<String>,
+    pub ahead: Option<u32>,
+    pub behind: Option<u32>,
+    pub base_ahead: Option<u32>,
+    pub pushed: Option<bool>,
+    /// `None` when status was skipped.
+    pub changes: Option<Changes>,
+    pub last_commit: Option<Commit>,
+    pub created_at: Option<u64>,
+    pub base_ref: Option<String>,
+    pub locked: bool,
+    pub lock_reason: Option<String>,
+    pub last_used: Option<u64>,
+    /// Only measured for `list --sort size`.
+    pub size: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct Commit {
+    pub sha: String,
+    pub subject: String,
+    /// Committer date in strict ISO 8601.
+    pub date: String,
+    pub timestamp: u64,
+}
+
+/// Gathers what `list` shows about a checkout. Without `with_status` only
+/// the metadata and HEAD files are read, without starting git, and `status`
+/// is `unknown`.
+pub fn collect(path: &Path, name: &str, main: bool, with_status: bool) -> SpaceInfo {
+    let space_meta = if main { None } else { meta::read(path) };
+    let mut info = SpaceInfo {
+        path: path.to_path_buf(),
+        name: space_meta.as_ref().map(|m| m.name.clone()).unwrap_or_else(|| name.to_string()),
+        id: if main { Some(1) } else { space_meta.as_ref().and_then(|m| m.id) },
+        main,
+        kind: if main { None } else { Some(Backend::of_space(path).as_str()) },
+        sparse: false,
+        branch: None,
+        status: "missing".to_string(),
+        upstream: None,
+        ahead: None,
+        behind: None,
+        base_ahead: None,
+        pushed: None,
+        changes: None,
+        last_commit: None,
+        created_at: space_meta.as_ref().map(|m| m.created_at).filter(|t| *t > 0),
+        base_ref: space_meta.as_ref().map(|m| m.base_ref.clone()).filter(|b| !b.is_empty()),
+        locked: space_meta.as_ref().is_some_and(|m| m.lock.is_some()),
+        lock_reason: space_meta.and_then(|m| m.lock).filter(|r| !r.is_empty()),
+        last_used: last_used(path),
+        size: None,
+    };
+    if !with_status {
+        info.branch = targets::head_branch(path);
+        info.status = if path.exists() { "unknown" } else { "missing" }.to_string();
+        return info;
+    }
+
+    info.sparse = !main && clone::is_sparse(path);
+    let status = targets::status(path, info.base_ref.as_deref());
+    info.status = status.state.to_string();
+    info.branch = status.branch;
+    info.upstream = status.upstream;
+    info.ahead = status.ahead;
+    info.behind = status.behind;
+    info.base_ahead = status.base_ahead;
+    info.pushed = status.pushed;
+    info.changes = Some(status.changes);
+    info.last_commit = last_commit(path);
+    info
+}
+
+impl SpaceInfo {
+    /// Short upstream summary for tables: `+2/-1`, `unpushed`, or `-`.
+    pub fn sync_label(&self) -> String {
+        match (self.ahead, self.behind, self.pushed) {
+            (Some(ahead), Some(behind), _) => format!("+{ahead}/-{behind}"),
+            (_, _, Some(false)) => "unpushed".to_string(),
+            _ => "-".to_string(),
+        }
+    }
+
+    /// Whether every commit on HEAD is already on the base ref, like
+    /// `git branch --merged`. A space with no commits of its own counts.
+    pub fn is_merged(&self) -> bool {
+        self.base_ahead == Some(0) && self.branch.is_some() && self.branch != self.base_ref
+    }
+
+    /// Creation time, or the last use for spaces made before metadata
+    /// recorded it.
+    pub fn age_timestamp(&self) -> Option<u64> {
+        self.created_at.or(self.last_used)
+    }
+}
+
+/// When the checkout last moved HEAD: the reflog's modification time, or
+/// HEAD's own when reflogs are off. The index is no use here because
+/// `git status` rewrites it.
+pub fn last_used(path: &Path) -> Option<u64> {
+    let git_dir = paths::git_dir(path)?;
+    let metadata = fs::metadata(git_dir.join("logs").join("HEAD"))
+        .or_else(|_| fs::metadata(git_dir.join("HEAD")))
+        .ok()?;
+    let modified = metadata.modified().ok()?;
+    modified.duration_since(UNIX_EPOCH).ok().map(|age| age.as_secs())
+}
+
+pub fn last_commit(path: &Path) -> Option<Commit> {
+    let out = git::git_stdout_opt(["log", "-1", "--format=%H%x00%cI%x00%ct%x00%s"], Some(path))?;
+    let mut parts = out.splitn(4, '\0');
+    Some(Commit {
+        sha: parts.next()?.to_string(),
+        date: parts.next()?.to_string(),
+        timestamp: parts.next()?.parse().ok()?,
+        subject: parts.next().unwrap_or_default().to_string(),
+    })
+}
+
+/// What `spaces status` shows about one checkout.
+pub struct Report {
+    pub name: String,
+    pub id: Option<u32>,
+    pub path: PathBuf,
+    pub base_ref: Option<String>,
+    pub status: targets::Status,
+    pub stashes: u32,
+    pub operation: Option<&'static str>,
+    pub unpushed: u32,
+    pub unpushed_commits: Vec<String>,
+    pub last_commit: Option<Commit>,
+}
+
+impl Report {
+    pub fn needs_attention(&self) -> bool {
+        self.status.state != "ok" || self.stashes > 0 || self.operation.is_some() || self.unpushed > 0
+    }
+}
+
+pub fn report(path: &Path, name: &str, main: bool, unpushed_limit: usize) -> Report {
+    let space_meta = if main { None } else { meta::read(path) };
+    let base_ref = space_meta.as_ref().map(|m| m.base_ref.clone()).filter(|b| !b.is_empty());
+    let status = targets::status(path, base_ref.as_deref());
+    let (unpushed, unpushed_commits) = if path.exists() {
+        targets::unpushed_commits(path, &status, unpushed_limit)
+    } else {
+        (0, Vec::new())
+    };
+    Report {
+        name: space_meta.as_ref().map(|m| m.name.clone()).unwrap_or_else(|| name.to_string()),
+        id: if main { Some(1) } else { space_meta.as_ref().and_then(|m| m.id) },
+        path: path.to_path_buf(),
+        base_ref,
+        stashes: if path.exists() { targets::stash_count(path) } else { 0 },
+        operation: targets::operation_in_progress(path),
+        unpushed,
+        unpushed_commits,
+        last_commit: last_commit(path),
+        status,
+    }
+}
@@ -0,0 +1,45 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::git;
+use crate::ui;
+
+pub fn version() -> Option<String> {
+    git::git_stdout_opt(["lfs", "version"], None)
+}
+
+/// Reports whether any tracked `.gitattributes` file routes paths through
+/// the LFS filter.
+pub fn uses_lfs(path: &Path) -> bool {
+    let Some(files) = git::git_stdout_opt(["ls-files", "--", ":(glob)**/.gitattributes"], Some(path)) else {
+        return false;
+    };
+    files.lines().any(|file| {
+        fs::read_to_string(path.join(file))
+            .map(|content| content.contains("filter=lfs"))
+            .unwrap_or(false)
+    })
+}
+
+/// The LFS object store shared by the mirror and every space. A bare mirror
+/// keeps LFS objects in `<mirror>/lfs`, so worktree spaces use it as is.
+pub fn storage_dir(mirror_dir: &Path) -> PathBuf {
+    mirror_dir.join("lfs")
+}
+
+pub fn space_storage(path: &Path) -> Option<PathBuf> {
+    git::git_stdout_opt(["config", "--get", "lfs.storage"], Some(path)).map(PathBuf::from)
+}
+
+pub fn prefetch(mirror_dir: &Path, branch: &str) -> Result<bool> {
+    if version().is_none() {
+        ui::log_warn("git-lfs not installed; skipping LFS prefetch");
+        return Ok(false);
+    }
+    ui::log_step(&format!("Prefetching LFS objects for {branch}..."));
+    git::git_check(["lfs", "fetch", "origin", branch], Some(mirror_dir))
+        .with_context(|| format!("prefetch LFS objects for {branch}"))?;
+    Ok(true)
+}
@@ -1,18 +1,32 @@
+mod archive;
 mod cli;
 mod clone;
 mod config;
 mod copy;
+mod du;
 mod git;
+mod group;
 mod hooks;
+mod info;
+mod lfs;
+mod meta;
 mod mirror;
+mod parallel;
 mod paths;
+mod pool;
+mod profile;
+mod rollback;
+mod submodules;
 mod targets;
+#[cfg(test)]
+mod testutil;
 mod ui;
 
 use std::collections::HashMap;
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use clap::Parser;
 
 use cli::{CleanArgs, Commands, CopyArgs, NewArgs, RmArgs, RunArgs};
@@ -29,28 +43,49 @@ fn run() -> Result<()> {
     match cli.command {
         Commands::New(args) => cmd_new(args),
         Commands::Rm(args) => cmd_rm(args),
-        Commands::Go(args) => cmd_go(&args.id),
+        Commands::Mv(args) => cmd_mv(args),
+        Commands::Archive(args) => cmd_archive(args),
+        Commands::Restore(args) => cmd_restore(args),
+        Commands::Lock(args) => cmd_lock(&args.target, Some(args.reason.as_deref().unwrap_or(""))),
+        Commands::Unlock(args) => cmd_lock(&args.id, None),
+        Commands::Go(args) => cmd_go(args),
         Commands::Run(args) => cmd_run(args),
         Commands::List(args) => cmd_list(args),
+        Commands::Status(args) => cmd_status(args),
+        Commands::Du(args) => cmd_du(args),
         Commands::Copy(args) => cmd_copy(args),
         Commands::Clean(args) => cmd_clean(args),
         Commands::Doctor => cmd_doctor(),
         Commands::Mirrors(args) => cmd_mirrors(args),
         Commands::Config(args) => cmd_config(args),
+        Commands::Detach(args) => cmd_detach(args),
+        Commands::Pool(args) => cmd_pool(args),
     }
 }
 
 fn cmd_new(args: NewArgs) -> Result<()> {
+    if let Some(group_name) = args.group.clone() {
+        return cmd_new_group(args, &group_name);
+    }
     let repo_root = paths::repo_root()?;
     let clones_dir = paths::clones_dir(&repo_root)?;
     let prefix = paths::clones_prefix(&repo_root)?;
     let mirror_dir = paths::mirror_dir(&repo_root)?;
 
-    if args.from.is_some() && args.branch.is_none() {
-        bail!("--from requires --branch");
-    }
+    let backend = clone::Backend::from_config(&repo_root)?;
+    let profile = match &args.profile {
+        Some(name) => Some(profile::load(name, &repo_root)?),
+        None => None,
+    };
+    let no_fetch = args.no_fetch || profile.as_ref().is_some_and(|p| p.no_fetch);
+    let no_copy = args.no_copy || profile.as_ref().is_some_and(|p| p.no_copy);
 
     let mut space = args.name.clone();
+    if space.is_none()
+        && let Some(number) = args.pr
+    {
+        space = Some(pr_space_name(number, &repo_root));
+    }
     if space.is_none() {
         if args.yes {
             bail!("Space name required in non-interactive mode");
@@ -62,54 +97,174 @@ fn cmd_new(args: NewArgs) -> Result<()> {
         space = Some(input);
     }
     let space = space.unwrap();
+    if space == "main" {
+        bail!("'main' is reserved for the main repository");
+    }
     let folder_name = paths::sanitize_branch_name(&space);
     let clone_path = clones_dir.join(format!("{prefix}{folder_name}"));
 
     ui::log_step(&format!("Creating space: {space}"));
     eprintln!("Location: {}", clone_path.display());
     eprintln!("Space: {space}");
-    if let Some(branch) = &args.branch {
+    let source_space = match &args.from_space {
+        Some(identifier) => Some(targets::resolve_target(identifier, &repo_root, &clones_dir, &prefix)?),
+        None => None,
+    };
+    let profile_prefix = profile.as_ref().and_then(|p| p.branch_prefix.clone());
+    let auto_branch = profile_prefix.is_some()
+        || config::is_truthy(&config::cfg_default("spaces.autoBranch", "SPACES_AUTO_BRANCH", "false", None, &repo_root)?);
+    let branch_prefix = match &profile_prefix {
+        Some(value) => value.clone(),
+        None => config::cfg_default("spaces.branchPrefix", "SPACES_BRANCH_PREFIX", "", None, &repo_root)?,
+    };
+    let derived_branch = format!("{branch_prefix}{}", paths::branch_from_name(&space));
+    let branch = match (args.pr, &source_space, &args.branch) {
+        (_, _, Some(branch)) => Some(branch.clone()),
+        (Some(number), _, None) => Some(format!("pr-{number}")),
+        (None, Some(_), None) => Some(derived_branch),
+        (None, None, None) if auto_branch && !args.detach => Some(derived_branch),
+        (None, None, None) => None,
+    };
+    // A profile prefix applies to every branch, explicit ones included;
+    // spaces.branchPrefix only shapes derived names.
+    let branch = match (branch, &profile_prefix) {
+        (Some(branch), Some(prefix)) if !branch.starts_with(prefix.as_str()) => Some(format!("{prefix}{branch}")),
+        (branch, _) => branch,
+    };
+    if branch.is_none() && !args.detach {
+        if args.from.is_some() {
+            bail!("--from requires a branch (--branch, spaces.autoBranch or a profile branchPrefix) or --detach");
+        }
+        if let Some(profile) = &profile
+            && let Some(base_ref) = &profile.base_ref
+        {
+            bail!(
+                "Profile {} sets baseRef {base_ref}, which requires a branch (--branch, spaces.autoBranch or a profile branchPrefix) or --detach",
+                profile.name
+            );
+        }
+    }
+    if let Some(branch) = &branch
+        && git::git_check(["check-ref-format", "--branch", branch], Some(&repo_root)).is_err()
+    {
+        bail!("Invalid branch name: {branch}");
+    }
+    if let Some(profile) = &profile {
+        eprintln!("Profile: {}", profile.name);
+    }
+    if let Some(branch) = &branch {
         eprintln!("Branch: {branch}");
     }
+    if let Some(source) = &source_space {
+        eprintln!("From space: {}", source.name);
+    }
 
     mirror::ensure_mirror(&repo_root, &mirror_dir)?;
-    if !args.no_fetch {
+    if !no_fetch {
         mirror::update_mirror(&repo_root, &mirror_dir)?;
     }
+    if let Some(number) = args.pr {
+        mirror::fetch_pull_request(&repo_root, &mirror_dir, number)?;
+    }
 
-    let base_ref = match &args.from {
-        Some(value) => value.clone(),
-        None => paths::default_branch(&repo_root)?,
+    let from = args.from.clone().or_else(|| profile.as_ref().and_then(|p| p.base_ref.clone()));
+    let base_ref = match (&from, args.pr, &source_space) {
+        (Some(value), _, _) => value.clone(),
+        (None, Some(number), _) => format!("refs/pull/{number}/head"),
+        (None, None, Some(source)) => git::git_stdout(["rev-parse", "HEAD"], Some(&source.path))?,
+        (None, None, None) => paths::default_branch(&repo_root)?,
     };
+    let dissociate =
+        config::is_truthy(&config::cfg_default("spaces.clone.dissociate", "", "false", None, &repo_root)?);
     let plan = clone::ClonePlan {
         path: clone_path.clone(),
-        branch: args.branch.clone(),
+        backend,
+        branch: branch.clone(),
         base_ref,
+        pull_request: args.pr,
+        from_space: source_space.as_ref().map(|source| source.path.clone()),
+        detach: args.detach,
+        sparse: if args.sparse.is_empty() {
+            config::cfg_get_all("spaces.sparse", config::Scope::Auto, &repo_root)
+        } else {
+            args.sparse.clone()
+        },
+        depth: match args.depth {
+            Some(depth) => Some(depth),
+            None => {
+                let value = config::cfg_default("spaces.clone.depth", "", "", None, &repo_root)?;
+                if value.is_empty() {
+                    None
+                } else {
+                    Some(value.parse().map_err(|_| anyhow::anyhow!("Invalid spaces.clone.depth: {value}"))?)
+                }
+            }
+        },
+        shallow_since: config_or(args.shallow_since.clone(), "spaces.clone.shallowSince", &repo_root)?,
+        filter: config_or(args.filter.clone(), "spaces.clone.filter", &repo_root)?,
+        lfs_storage: lfs_storage(&repo_root, &mirror_dir),
+        dissociate,
     };
 
-    clone::create_clone(&repo_root, &mirror_dir, &plan)?;
+    let poolable = !args.no_pool
+        && backend == clone::Backend::Clone
+        && plan.sparse.is_empty()
+        && plan.depth.is_none()
+        && plan.shallow_since.is_none()
+        && plan.filter.is_none()
+        && plan.pull_request.is_none()
+        && plan.from_space.is_none()
+        && profile.is_none()
+        && !no_copy;
+    if clone_path.exists() {
+        bail!("Clone already exists: {}", clone_path.display());
+    }
+    rollback::install_interrupt_handler();
+    let guard = rollback::SpaceGuard::new(&clone_path, &mirror_dir, args.keep_on_failure);
+
+    let claimed = if poolable { pool::claim(&clones_dir, &clone_path)? } else { None };
+    if claimed.is_some() {
+        ui::log_info("Claimed a clone from the pool");
+        clone::refresh_claimed(&mirror_dir, &plan)?;
+        if dissociate {
+            clone::dissociate(&clone_path)?;
+        }
+    } else {
+        clone::create_clone(&repo_root, &mirror_dir, &plan)?;
+    }
+    rollback::check_interrupted()?;
 
-    if !args.no_copy {
-        let mut includes = config::cfg_get_all("spaces.copy.include", config::Scope::Auto, &repo_root);
-        let worktree_include = copy::parse_pattern_file(&repo_root.join(".worktreeinclude"))?;
-        let spaces_include = copy::parse_pattern_file(&repo_root.join(".spacesinclude"))?;
-        includes.extend(worktree_include);
-        includes.extend(spaces_include);
-        includes = dedupe(includes);
+    if !args.no_submodules {
+        submodules::init(&clone_path, &mirror_dir, !no_fetch, dissociate)?;
+        rollback::check_interrupted()?;
+    }
 
-        let excludes = config::cfg_get_all("spaces.copy.exclude", config::Scope::Auto, &repo_root);
+    let id = meta::allocate_id(&repo_root, &clones_dir, &prefix)?;
+    let space_meta = meta::SpaceMeta {
+        id: Some(id),
+        name: space.clone(),
+        folder: format!("{prefix}{folder_name}"),
+        branch,
+        base_ref: plan.base_ref.clone(),
+        created_at: meta::now(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        mirror_head: git::git_stdout_opt(["rev-parse", "HEAD"], Some(&mirror_dir)),
+        profile: profile.as_ref().map(|p| p.name.clone()),
+        lock: None,
+    };
+    meta::write(&clone_path, &space_meta)?;
+    eprintln!("Id: {id}");
 
-        if !includes.is_empty() {
-            ui::log_step("Copying files...");
-            copy::copy_patterns(&repo_root, &clone_path, &includes, &excludes, false)?;
-        }
+    // Pool entries were copied into when they were filled.
+    if !no_copy && claimed.is_none() {
+        copy_into_space(&repo_root, &clone_path, profile.as_ref())?;
+        rollback::check_interrupted()?;
+    }
 
-        let dir_includes = config::cfg_get_all("spaces.copy.includeDirs", config::Scope::Auto, &repo_root);
-        let dir_excludes = config::cfg_get_all("spaces.copy.excludeDirs", config::Scope::Auto, &repo_root);
-        if !dir_includes.is_empty() {
-            ui::log_step("Copying directories...");
-            copy::copy_directories(&repo_root, &clone_path, &dir_includes, &dir_excludes)?;
-        }
+    if let Some(source) = &source_space {
+        ui::log_step(&format!("Carrying over changes from {}...", source.name));
+        clone::carry_changes(&source.path, &clone_path)?;
+        rollback::check_interrupted()?;
     }
 
     let mut envs = HashMap::new();
@@ -119,16 +274,210 @@ fn cmd_new(args: NewArgs) -> Result<()> {
     if let Some(branch) = targets::current_branch(&clone_path) {
         envs.insert("BRANCH".to_string(), branch);
     }
+    let profile_name = profile.as_ref().map(|p| p.name.as_str());
+    if let Some(name) = profile_name {
+        envs.insert("PROFILE".to_string(), name.to_string());
+    }
 
-    hooks::run_hooks("postCreate", &repo_root, &clone_path, &envs)?;
+    // Pool entries ran postCreate when they were filled; postClaim fixes up
+    // anything that recorded the pool path.
+    match &claimed {
+        Some(entry) => {
+            envs.insert("POOL_PATH".to_string(), pool::built_at(entry).to_string_lossy().to_string());
+            hooks::run_hooks("postClaim", profile_name, &repo_root, &clone_path, &envs)?;
+        }
+        None => hooks::run_hooks("postCreate", profile_name, &repo_root, &clone_path, &envs)?,
+    }
+    rollback::check_interrupted()?;
 
+    guard.commit();
     ui::log_info(&format!("Space created: {}", clone_path.display()));
     Ok(())
 }
 
+fn cmd_new_group(args: NewArgs, group_name: &str) -> Result<()> {
+    let root = group::config_root();
+    let group = group::load(group_name, &root)?;
+
+    let space = match args.name.clone() {
+        Some(name) => name,
+        None if args.yes => bail!("Space name required in non-interactive mode"),
+        None => {
+            let input = ui::prompt_input("Enter space name:")?;
+            if input.is_empty() {
+                bail!("Space name required");
+            }
+            input
+        }
+    };
+    let path = group::space_path(&root, &group.name, &space)?;
+    if path.exists() {
+        bail!("Group space already exists: {}", path.display());
+    }
+
+    // Every member must end up on the same branch, so group spaces always
+    // get one.
+    let branch = match &args.branch {
+        Some(branch) => branch.clone(),
+        None => {
+            let branch_prefix = config::cfg_default("spaces.branchPrefix", "SPACES_BRANCH_PREFIX", "", None, &root)?;
+            format!("{branch_prefix}{}", paths::branch_from_name(&space))
+        }
+    };
+    if git::git_check(["check-ref-format", "--branch", &branch], Some(&group.repos[0])).is_err() {
+        bail!("Invalid branch name: {branch}");
+    }
+
+    ui::log_step(&format!("Creating group space: {space}"));
+    eprintln!("Location: {}", path.display());
+    eprintln!("Group: {}", group.name);
+    eprintln!("Branch: {branch}");
+
+    rollback::install_interrupt_handler();
+    match create_group_members(&args, &group, &space, &path, &branch) {
+        Ok(()) => {
+            ui::log_info(&format!("Group space created: {}", path.display()));
+            Ok(())
+        }
+        Err(err) => {
+            if !args.keep_on_failure && path.exists() {
+                let _ = std::fs::remove_dir_all(&path);
+            }
+            Err(err)
+        }
+    }
+}
+
+fn create_group_members(args: &NewArgs, group: &group::Group, space: &str, path: &Path, branch: &str) -> Result<()> {
+    std::fs::create_dir_all(path).with_context(|| format!("create group space {}", path.display()))?;
+    let mut guards = Vec::new();
+    let mut members = Vec::new();
+
+    for repo in &group.repos {
+        let member = group::member_name(repo);
+        let clone_path = path.join(&member);
+        let mirror_dir = paths::mirror_dir(repo)?;
+        ui::log_step(&format!("Member: {member}"));
+
+        mirror::ensure_mirror(repo, &mirror_dir)?;
+        if !args.no_fetch {
+            mirror::update_mirror(repo, &mirror_dir)?;
+        }
+        let dissociate =
+            config::is_truthy(&config::cfg_default("spaces.clone.dissociate", "", "false", None, repo)?);
+        let plan = clone::ClonePlan {
+            path: clone_path.clone(),
+            backend: clone::Backend::from_config(repo)?,
+            branch: Some(branch.to_string()),
+            base_ref: match &args.from {
+                Some(from) => from.clone(),
+                None => paths::default_branch(repo)?,
+            },
+            pull_request: None,
+            from_space: None,
+            detach: false,
+            sparse: Vec::new(),
+            depth: args.depth,
+            shallow_since: args.shallow_since.clone(),
+            filter: args.filter.clone(),
+            lfs_storage: lfs_storage(repo, &mirror_dir),
+            dissociate,
+        };
+
+        guards.push(rollback::SpaceGuard::new(&clone_path, &mirror_dir, args.keep_on_failure));
+        clone::create_clone(repo, &mirror_dir, &plan)?;
+        rollback::check_interrupted()?;
+        if !args.no_submodules {
+            submodules::init(&clone_path, &mirror_dir, !args.no_fetch, dissociate)?;
+            rollback::check_interrupted()?;
+        }
+
+        // Members live outside the repo's clones dir, where ids are looked
+        // up, so they are addressed through the group instead.
+        let space_meta = meta::SpaceMeta {
+            id: None,
+            name: space.to_string(),
+            folder: member.clone(),
+            branch: Some(branch.to_string()),
+            base_ref: plan.base_ref.clone(),
+            created_at: meta::now(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            mirror_head: git::git_stdout_opt(["rev-parse", "HEAD"], Some(&mirror_dir)),
+            profile: None,
+            lock: None,
+        };
+        meta::write(&clone_path, &space_meta)?;
+
+        if !args.no_copy {
+            copy_into_space(repo, &clone_path, None)?;
+            rollback::check_interrupted()?;
+        }
+
+        let mut envs = HashMap::new();
+        envs.insert("REPO_ROOT".to_string(), repo.to_string_lossy().to_string());
+        envs.insert("CLONE_PATH".to_string(), clone_path.to_string_lossy().to_string());
+        envs.insert("SPACE".to_string(), space.to_string());
+        envs.insert("BRANCH".to_string(), branch.to_string());
+        envs.insert("GROUP".to_string(), group.name.clone());
+        envs.insert("GROUP_PATH".to_string(), path.to_string_lossy().to_string());
+        hooks::run_hooks("postCreate", None, repo, &clone_path, &envs)?;
+        rollback::check_interrupted()?;
+
+        members.push(group::Member {
+            name: member,
+            repo: repo.clone(),
+            path: clone_path,
+        });
+    }
+
+    group::write_record(&group::GroupSpace {
+        group: group.name.clone(),
+        name: space.to_string(),
+        path: path.to_path_buf(),
+        branch: branch.to_string(),
+        members,
+    })?;
+    for guard in guards {
+        guard.commit();
+    }
+    Ok(())
+}
+
+fn copy_into_space(repo_root: &Path, clone_path: &Path, profile: Option<&profile::Profile>) -> Result<()> {
+    let mut includes = config::cfg_get_all("spaces.copy.include", config::Scope::Auto, repo_root);
+    let worktree_include = copy::parse_pattern_file(&repo_root.join(".worktreeinclude"))?;
+    let spaces_include = copy::parse_pattern_file(&repo_root.join(".spacesinclude"))?;
+    includes.extend(worktree_include);
+    includes.extend(spaces_include);
+    let mut excludes = config::cfg_get_all("spaces.copy.exclude", config::Scope::Auto, repo_root);
+    let mut dir_includes = config::cfg_get_all("spaces.copy.includeDirs", config::Scope::Auto, repo_root);
+    let mut dir_excludes = config::cfg_get_all("spaces.copy.excludeDirs", config::Scope::Auto, repo_root);
+    if let Some(profile) = profile {
+        includes.extend(profile.copy_include.iter().cloned());
+        excludes.extend(profile.copy_exclude.iter().cloned());
+        dir_includes.extend(profile.copy_include_dirs.iter().cloned());
+        dir_excludes.extend(profile.copy_exclude_dirs.iter().cloned());
+    }
+    includes = dedupe(includes);
+
+    if !includes.is_empty() {
+        ui::log_step("Copying files...");
+        copy::copy_patterns(repo_root, clone_path, &includes, &excludes, false)?;
+    }
+
+    if !dir_includes.is_empty() {
+        ui::log_step("Copying directories...");
+        copy::copy_directories(repo_root, clone_path, &dir_includes, &dir_excludes)?;
+    }
+    Ok(())
+}
+
 fn cmd_rm(args: RmArgs) -> Result<()> {
     if args.targets.is_empty() {
-        bail!("Usage: spaces rm <space|id> [<space|id>...] [--force] [--yes]");
+        bail!("Usage: spaces rm <space|id> [<space|id>...] [--group <g>] [--force] [--yes]");
+    }
+    if let Some(group_name) = &args.group {
+        return cmd_rm_group(group_name, &args.targets, args.force);
     }
 
     let repo_root = paths::repo_root()?;
@@ -141,6 +490,17 @@ fn cmd_rm(args: RmArgs) -> Result<()> {
             ui::log_error("Cannot remove main repository");
             continue;
         }
+        if let Err(err) = confirm_inexact(&target, &identifier, "remove") {
+            ui::log_error(&err.to_string());
+            continue;
+        }
+
+        if let Some(reason) = target.meta.as_ref().and_then(|m| m.lock.as_deref())
+            && !args.force
+        {
+            ui::log_error(&locked_message(&target.name, reason));
+            continue;
+        }
 
         ui::log_step(&format!("Removing space: {}", target.path.display()));
 
@@ -149,8 +509,12 @@ fn cmd_rm(args: RmArgs) -> Result<()> {
         envs.insert("CLONE_PATH".to_string(), target.path.to_string_lossy().to_string());
         envs.insert("SPACE".to_string(), target.name.clone());
         envs.insert("BRANCH".to_string(), target.branch.clone());
+        let profile_name = target.meta.as_ref().and_then(|m| m.profile.clone());
+        if let Some(name) = &profile_name {
+            envs.insert("PROFILE".to_string(), name.clone());
+        }
 
-        if let Err(err) = hooks::run_hooks("preRemove", &repo_root, &target.path, &envs) {
+        if let Err(err) = hooks::run_hooks("preRemove", profile_name.as_deref(), &repo_root, &target.path, &envs) {
             if !args.force {
                 ui::log_error(&format!("Pre-remove hook failed: {err}"));
                 continue;
@@ -158,33 +522,492 @@ fn cmd_rm(args: RmArgs) -> Result<()> {
             ui::log_warn("Pre-remove hook failed; continuing due to --force");
         }
 
-        safe_remove_clone(&target.path, &clones_dir)?;
+        if let Err(err) = safe_remove_clone(&target.path, &clones_dir, args.force) {
+            ui::log_error(&err.to_string());
+            continue;
+        }
+
+        let _ = hooks::run_hooks("postRemove", profile_name.as_deref(), &repo_root, &repo_root, &envs);
+    }
+
+    Ok(())
+}
+
+fn cmd_rm_group(group_name: &str, spaces: &[String], force: bool) -> Result<()> {
+    let root = group::config_root();
+    for space in spaces {
+        let group_space = group::resolve(&root, group_name, space)?;
+        let locked: Vec<String> = group_space
+            .members
+            .iter()
+            .filter_map(|m| meta::read(&m.path).and_then(|meta| meta.lock).map(|reason| (m, reason)))
+            .map(|(m, reason)| format!("{}{}", m.name, reason_suffix(&reason)))
+            .collect();
+        if !locked.is_empty() && !force {
+            ui::log_error(&format!(
+                "Group space '{space}' has locked members: {}; use --force to override",
+                locked.join(", ")
+            ));
+            continue;
+        }
+
+        // Check every member up front so a dirty worktree does not leave the
+        // group half removed.
+        let dirty: Vec<&str> = group_space
+            .members
+            .iter()
+            .filter(|m| m.path.exists() && clone::Backend::of_space(&m.path) == clone::Backend::Worktree)
+            .filter(|m| !targets::status(&m.path, None).changes.is_empty())
+            .map(|m| m.name.as_str())
+            .collect();
+        if !dirty.is_empty() && !force {
+            ui::log_error(&format!(
+                "Group space '{space}' has worktree members with local changes: {}; use --force to override",
+                dirty.join(", ")
+            ));
+            continue;
+        }
+
+        ui::log_step(&format!("Removing group space: {}", group_space.path.display()));
+        let member_envs = |member: &group::Member| {
+            let mut envs = HashMap::new();
+            envs.insert("REPO_ROOT".to_string(), member.repo.to_string_lossy().to_string());
+            envs.insert("CLONE_PATH".to_string(), member.path.to_string_lossy().to_string());
+            envs.insert("SPACE".to_string(), group_space.name.clone());
+            envs.insert("BRANCH".to_string(), group_space.branch.clone());
+            envs.insert("GROUP".to_string(), group_space.group.clone());
+            envs.insert("GROUP_PATH".to_string(), group_space.path.to_string_lossy().to_string());
+            envs
+        };
+
+        let mut hook_failed = false;
+        for member in group_space.members.iter().filter(|m| m.path.exists()) {
+            if let Err(err) = hooks::run_hooks("preRemove", None, &member.repo, &member.path, &member_envs(member)) {
+                ui::log_error(&format!("Pre-remove hook failed for {}: {err}", member.name));
+                hook_failed = true;
+            }
+        }
+        if hook_failed {
+            if !force {
+                continue;
+            }
+            ui::log_warn("Pre-remove hook failed; continuing due to --force");
+        }
+
+        for member in group_space.members.iter().filter(|m| m.path.exists()) {
+            safe_remove_clone(&member.path, &group_space.path, force)?;
+        }
+        std::fs::remove_dir_all(&group_space.path)
+            .with_context(|| format!("remove group space {}", group_space.path.display()))?;
+
+        for member in &group_space.members {
+            let _ = hooks::run_hooks("postRemove", None, &member.repo, &member.repo, &member_envs(member));
+        }
+    }
+    Ok(())
+}
+
+fn cmd_mv(args: cli::MvArgs) -> Result<()> {
+    let repo_root = paths::repo_root()?;
+    let clones_dir = paths::clones_dir(&repo_root)?;
+    let prefix = paths::clones_prefix(&repo_root)?;
+
+    let target = targets::resolve_target(&args.from, &repo_root, &clones_dir, &prefix)?;
+    if target.is_main {
+        bail!("Cannot move main repository");
+    }
+    confirm_inexact(&target, &args.from, "move")?;
+    if let Some(reason) = target.meta.as_ref().and_then(|m| m.lock.as_deref())
+        && !args.force
+    {
+        bail!("{}", locked_message(&target.name, reason));
+    }
+    if args.to == "main" {
+        bail!("'main' is reserved for the main repository");
+    }
+    let folder_name = paths::sanitize_branch_name(&args.to);
+    if folder_name.is_empty() {
+        bail!("Invalid space name: {}", args.to);
+    }
+    let new_path = clones_dir.join(format!("{prefix}{folder_name}"));
+    if new_path.exists() {
+        bail!("Clone already exists: {}", new_path.display());
+    }
+
+    let new_branch = match &args.branch_name {
+        Some(name) => Some(name.clone()),
+        None if args.rename_branch => {
+            let branch_prefix =
+                config::cfg_default("spaces.branchPrefix", "SPACES_BRANCH_PREFIX", "", None, &repo_root)?;
+            Some(format!("{branch_prefix}{}", paths::branch_from_name(&args.to)))
+        }
+        None => None,
+    };
+    if let Some(branch) = &new_branch {
+        if target.branch == "(detached)" {
+            bail!("Renaming the branch requires a space on a branch");
+        }
+        let base = target.meta.as_ref().map(|m| m.base_ref.clone()).unwrap_or_default();
+        let base = base.strip_prefix("origin/").unwrap_or(&base);
+        if target.branch == paths::default_branch(&repo_root)? || target.branch == base {
+            bail!("{} is on its base branch {}; refusing to rename it", target.name, target.branch);
+        }
+        if git::git_check(["check-ref-format", "--branch", branch], Some(&repo_root)).is_err() {
+            bail!("Invalid branch name: {branch}");
+        }
+        let branch_ref = format!("refs/heads/{branch}");
+        if git::git_check(["show-ref", "--verify", "--quiet", &branch_ref], Some(&target.path)).is_ok() {
+            bail!("Branch {branch} already exists in {}", target.name);
+        }
+    }
+
+    let profile_name = target.meta.as_ref().and_then(|m| m.profile.clone());
+    let mut envs = HashMap::new();
+    envs.insert("REPO_ROOT".to_string(), repo_root.to_string_lossy().to_string());
+    envs.insert("CLONE_PATH".to_string(), target.path.to_string_lossy().to_string());
+    envs.insert("SPACE".to_string(), target.name.clone());
+    envs.insert("BRANCH".to_string(), target.branch.clone());
+    envs.insert("NEW_CLONE_PATH".to_string(), new_path.to_string_lossy().to_string());
+    envs.insert("NEW_SPACE".to_string(), args.to.clone());
+    if let Some(name) = &profile_name {
+        envs.insert("PROFILE".to_string(), name.clone());
+    }
 
-        let _ = hooks::run_hooks("postRemove", &repo_root, &repo_root, &envs);
+    if let Err(err) = hooks::run_hooks("preMove", profile_name.as_deref(), &repo_root, &target.path, &envs) {
+        if !args.force {
+            bail!("Pre-move hook failed: {err}");
+        }
+        ui::log_warn("Pre-move hook failed; continuing due to --force");
     }
 
+    ui::log_step(&format!("Moving space: {} -> {}", target.name, args.to));
+    if let Some(branch) = &new_branch {
+        git::git_check(["branch", "-m", &target.branch, branch], Some(&target.path))?;
+    }
+    // Puts the branch name back; the folder is moved back by the caller.
+    let undo_branch = |path: &Path| {
+        if let Some(branch) = &new_branch {
+            let _ = git::git_check(["branch", "-m", branch, &target.branch], Some(path));
+        }
+    };
+    if let Err(err) = clone::move_space(&target.path, &new_path) {
+        undo_branch(&target.path);
+        return Err(err);
+    }
+    if let Some(mut space_meta) = meta::read(&new_path) {
+        space_meta.name = args.to.clone();
+        space_meta.folder = format!("{prefix}{folder_name}");
+        if let Some(branch) = &new_branch {
+            space_meta.branch = Some(branch.clone());
+        }
+        if let Err(err) = meta::write(&new_path, &space_meta) {
+            undo_branch(&new_path);
+            if clone::move_space(&new_path, &target.path).is_err() {
+                ui::log_warn(&format!("Could not move {} back", new_path.display()));
+            }
+            return Err(err.context("Failed to update space metadata"));
+        }
+    }
+
+    if let Some(branch) = &new_branch {
+        ui::log_info(&format!("Renamed branch: {} -> {branch}", target.branch));
+        // `branch -m` keeps tracking the old remote branch, which the next
+        // push would update under the old name.
+        if git::git_stdout_opt(["config", &format!("branch.{branch}.merge")], Some(&new_path)).is_some() {
+            if git::git_check(["branch", "--unset-upstream", branch], Some(&new_path)).is_ok() {
+                ui::log_warn(&format!("Removed upstream of {branch}; set one with 'git push -u'"));
+            } else {
+                ui::log_warn(&format!("Could not remove the upstream of {branch}"));
+            }
+        }
+    }
+
+    let mut envs = HashMap::new();
+    envs.insert("REPO_ROOT".to_string(), repo_root.to_string_lossy().to_string());
+    envs.insert("CLONE_PATH".to_string(), new_path.to_string_lossy().to_string());
+    envs.insert("SPACE".to_string(), args.to.clone());
+    envs.insert("OLD_CLONE_PATH".to_string(), target.path.to_string_lossy().to_string());
+    envs.insert("OLD_SPACE".to_string(), target.name.clone());
+    if let Some(branch) = targets::current_branch(&new_path) {
+        envs.insert("BRANCH".to_string(), branch);
+    }
+    if let Some(name) = &profile_name {
+        envs.insert("PROFILE".to_string(), name.clone());
+    }
+    hooks::run_hooks("postMove", profile_name.as_deref(), &repo_root, &new_path, &envs)?;
+
+    ui::log_info(&format!("Space moved: {}", new_path.display()));
     Ok(())
 }
 
-fn cmd_go(id: &str) -> Result<()> {
+fn cmd_archive(args: cli::ArchiveArgs) -> Result<()> {
     let repo_root = paths::repo_root()?;
     let clones_dir = paths::clones_dir(&repo_root)?;
     let prefix = paths::clones_prefix(&repo_root)?;
+    let archive_dir = archive::archive_dir(&repo_root, &clones_dir)?;
 
-    let target = targets::resolve_target(id, &repo_root, &clones_dir, &prefix)?;
+    if args.list {
+        let archives = archive::list(&archive_dir)?;
+        if archives.is_empty() {
+            println!("No archived spaces");
+            return Ok(());
+        }
+        println!("{:<4} {:<24} {:<24} {:<10} CONTENTS", "ID", "SPACE", "BRANCH", "ARCHIVED");
+        println!("{:<4} {:<24} {:<24} {:<10} --------", "--", "-----", "------", "--------");
+        for entry in archives {
+            let id = entry.meta.id.map(|id| id.to_string()).unwrap_or_else(|| "-".to_string());
+            let branch = entry.head_branch.clone().unwrap_or_else(|| "(detached)".to_string());
+            let age = format!("{} ago", ui::format_age(meta::now().saturating_sub(entry.archived_at)));
+            let mut contents = Vec::new();
+            if !entry.branches.is_empty() {
+                contents.push(format!("unpushed: {}", entry.branches.join(",")));
+            }
+            if entry.has_changes() {
+                contents.push("local changes".to_string());
+            }
+            if contents.is_empty() {
+                contents.push("-".to_string());
+            }
+            println!("{:<4} {:<24} {:<24} {:<10} {}", id, entry.meta.name, branch, age, contents.join("; "));
+        }
+        return Ok(());
+    }
+
+    let Some(identifier) = args.target else {
+        bail!("Usage: spaces archive <space|id> [--force] | spaces archive --list");
+    };
+    let target = targets::resolve_target(&identifier, &repo_root, &clones_dir, &prefix)?;
+    if target.is_main {
+        bail!("Cannot archive main repository");
+    }
+    confirm_inexact(&target, &identifier, "archive")?;
+
+    if let Some(reason) = target.meta.as_ref().and_then(|m| m.lock.as_deref())
+        && !args.force
+    {
+        bail!("{}", locked_message(&target.name, reason));
+    }
+
+    let space_meta = space_meta_or_default(&target, &repo_root)?;
+    let folder = space_meta.folder.clone();
+
+    ui::log_step(&format!("Archiving space: {}", target.name));
+    let entry = archive::create(&target.path, &archive_dir.join(&folder), &space_meta)?;
+    if !entry.branches.is_empty() {
+        ui::log_info(&format!("Saved unpushed branches: {}", entry.branches.join(", ")));
+    }
+    if entry.has_changes() {
+        ui::log_info("Saved local changes");
+    }
+
+    let mut envs = HashMap::new();
+    envs.insert("REPO_ROOT".to_string(), repo_root.to_string_lossy().to_string());
+    envs.insert("CLONE_PATH".to_string(), target.path.to_string_lossy().to_string());
+    envs.insert("SPACE".to_string(), target.name.clone());
+    envs.insert("BRANCH".to_string(), target.branch.clone());
+    let profile_name = space_meta.profile.clone();
+    if let Some(name) = &profile_name {
+        envs.insert("PROFILE".to_string(), name.clone());
+    }
+
+    if let Err(err) = hooks::run_hooks("preRemove", profile_name.as_deref(), &repo_root, &target.path, &envs) {
+        if !args.force {
+            let _ = std::fs::remove_dir_all(&entry.path);
+            bail!("Pre-remove hook failed: {err}");
+        }
+        ui::log_warn("Pre-remove hook failed; continuing due to --force");
+    }
+
+    // The archive holds the local changes, so a dirty worktree may go.
+    safe_remove_clone(&target.path, &clones_dir, true)?;
+    let _ = hooks::run_hooks("postRemove", profile_name.as_deref(), &repo_root, &repo_root, &envs);
+
+    ui::log_info(&format!("Archived to {}", entry.path.display()));
+    eprintln!("Restore with: spaces restore {}", space_meta.name);
+    Ok(())
+}
+
+fn cmd_restore(args: cli::RestoreArgs) -> Result<()> {
+    let repo_root = paths::repo_root()?;
+    let clones_dir = paths::clones_dir(&repo_root)?;
+    let mirror_dir = paths::mirror_dir(&repo_root)?;
+    let archive_dir = archive::archive_dir(&repo_root, &clones_dir)?;
+
+    let entry = archive::find(&archive_dir, &args.name)?;
+    let clone_path = clones_dir.join(&entry.meta.folder);
+    if clone_path.exists() {
+        bail!("Clone already exists: {}", clone_path.display());
+    }
+    let profile = match &entry.meta.profile {
+        Some(name) => match profile::load(name, &repo_root) {
+            Ok(profile) => Some(profile),
+            Err(err) => {
+                ui::log_warn(&format!("{err}; restoring without profile"));
+                None
+            }
+        },
+        None => None,
+    };
+
+    ui::log_step(&format!("Restoring space: {}", entry.meta.name));
+    eprintln!("Location: {}", clone_path.display());
+
+    mirror::ensure_mirror(&repo_root, &mirror_dir)?;
+    if !args.no_fetch {
+        mirror::update_mirror(&repo_root, &mirror_dir)?;
+    }
+
+    // Start from the archived HEAD when the mirror has it; commits that only
+    // exist in the bundle are replayed on top of the default branch.
+    let head_commit = format!("{}^{{commit}}", entry.head);
+    let base_ref = if git::git_check(["cat-file", "-e", &head_commit], Some(&mirror_dir)).is_ok() {
+        entry.head.clone()
+    } else {
+        paths::default_branch(&repo_root)?
+    };
+    let dissociate =
+        config::is_truthy(&config::cfg_default("spaces.clone.dissociate", "", "false", None, &repo_root)?);
+    let plan = clone::ClonePlan {
+        path: clone_path.clone(),
+        backend: entry.backend,
+        branch: None,
+        base_ref,
+        pull_request: None,
+        from_space: None,
+        detach: true,
+        sparse: entry.sparse.clone(),
+        depth: entry.depth,
+        shallow_since: None,
+        filter: entry.filter.clone(),
+        lfs_storage: lfs_storage(&repo_root, &mirror_dir),
+        dissociate,
+    };
+
+    rollback::install_interrupt_handler();
+    let guard = rollback::SpaceGuard::new(&clone_path, &mirror_dir, args.keep_on_failure);
+    clone::create_clone(&repo_root, &mirror_dir, &plan)?;
+    rollback::check_interrupted()?;
+
+    archive::apply(&entry, &clone_path)?;
+    rollback::check_interrupted()?;
+
+    submodules::init(&clone_path, &mirror_dir, !args.no_fetch, dissociate)?;
+    rollback::check_interrupted()?;
+
+    let mut space_meta = entry.meta;
+    if space_meta.id.is_none() {
+        let prefix = paths::clones_prefix(&repo_root)?;
+        space_meta.id = Some(meta::allocate_id(&repo_root, &clones_dir, &prefix)?);
+    }
+    space_meta.mirror_head = git::git_stdout_opt(["rev-parse", "HEAD"], Some(&mirror_dir));
+    meta::write(&clone_path, &space_meta)?;
+
+    if !args.no_copy {
+        copy_into_space(&repo_root, &clone_path, profile.as_ref())?;
+        rollback::check_interrupted()?;
+    }
+
+    let mut envs = HashMap::new();
+    envs.insert("REPO_ROOT".to_string(), repo_root.to_string_lossy().to_string());
+    envs.insert("CLONE_PATH".to_string(), clone_path.to_string_lossy().to_string());
+    envs.insert("SPACE".to_string(), space_meta.name.clone());
+    if let Some(branch) = targets::current_branch(&clone_path) {
+        envs.insert("BRANCH".to_string(), branch);
+    }
+    let profile_name = profile.as_ref().map(|p| p.name.as_str());
+    if let Some(name) = profile_name {
+        envs.insert("PROFILE".to_string(), name.to_string());
+    }
+    hooks::run_hooks("postCreate", profile_name, &repo_root, &clone_path, &envs)?;
+    rollback::check_interrupted()?;
+
+    guard.commit();
+    std::fs::remove_dir_all(&entry.path)
+        .with_context(|| format!("remove archive {}", entry.path.display()))?;
+    ui::log_info(&format!("Space restored: {}", clone_path.display()));
+    Ok(())
+}
+
+fn cmd_lock(identifier: &str, reason: Option<&str>) -> Result<()> {
+    let repo_root = paths::repo_root()?;
+    let clones_dir = paths::clones_dir(&repo_root)?;
+    let prefix = paths::clones_prefix(&repo_root)?;
+
+    let target = targets::resolve_target(identifier, &repo_root, &clones_dir, &prefix)?;
+    if target.is_main {
+        bail!("Main repository is never removed by spaces; nothing to lock");
+    }
+    if target.meta.is_none() {
+        meta::write(&target.path, &space_meta_or_default(&target, &repo_root)?)?;
+    }
+    meta::set_lock(&target.path, reason)?;
+    match reason {
+        Some(_) => ui::log_info(&format!("Locked space: {}", target.name)),
+        None => ui::log_info(&format!("Unlocked space: {}", target.name)),
+    }
+    Ok(())
+}
+
+fn cmd_go(args: cli::GoArgs) -> Result<()> {
+    if let Some(group_name) = &args.group {
+        let group_space = group::resolve(&group::config_root(), group_name, &args.id)?;
+        eprintln!("Group: {}", group_space.group);
+        eprintln!("Space: {}", group_space.name);
+        eprintln!("Branch: {}", group_space.branch);
+        for member in &group_space.members {
+            let branch = targets::current_branch(&member.path).unwrap_or_else(|| "(detached)".to_string());
+            eprintln!("Member: {} ({branch}, {})", member.name, targets::status(&member.path, None).state);
+        }
+        println!("{}", group_space.path.display());
+        return Ok(());
+    }
+
+    let repo_root = paths::repo_root()?;
+    let clones_dir = paths::clones_dir(&repo_root)?;
+    let prefix = paths::clones_prefix(&repo_root)?;
+
+    let target = targets::resolve_target(&args.id, &repo_root, &clones_dir, &prefix)?;
     if target.is_main {
         eprintln!("Main repo");
     } else {
         eprintln!("Space: {}", target.name);
     }
     eprintln!("Branch: {}", target.branch);
+    if let Some(meta) = &target.meta {
+        if let Some(id) = meta.id {
+            eprintln!("Id: {id}");
+        }
+        eprintln!("Base: {}", meta.base_ref);
+        if meta.created_at > 0 {
+            eprintln!("Created: {} ago", ui::format_age(meta::now().saturating_sub(meta.created_at)));
+        }
+        if let Some(reason) = &meta.lock {
+            eprintln!("Locked: yes{}", reason_suffix(reason));
+        }
+    }
     println!("{}", target.path.display());
     Ok(())
 }
 
 fn cmd_run(args: RunArgs) -> Result<()> {
     if args.cmd.is_empty() {
-        bail!("Usage: spaces run <space|id> -- <command...>");
+        bail!("Usage: spaces run <space|id> [--group <g>] -- <command...>");
+    }
+    if let Some(group_name) = &args.group {
+        let group_space = group::resolve(&group::config_root(), group_name, &args.id)?;
+        let mut failed = Vec::new();
+        for member in &group_space.members {
+            ui::log_step(&format!("Running in: {}/{}", group_space.name, member.name));
+            let mut cmd = std::process::Command::new(&args.cmd[0]);
+            cmd.args(&args.cmd[1..]).current_dir(&member.path);
+            if !cmd.status()?.success() {
+                failed.push(member.name.clone());
+            }
+        }
+        if !failed.is_empty() {
+            bail!("Command failed in: {}", failed.join(", "));
+        }
+        return Ok(());
     }
 
     let repo_root = paths::repo_root()?;
@@ -213,62 +1036,348 @@ fn cmd_list(args: cli::ListArgs) -> Result<()> {
     let clones_dir = paths::clones_dir(&repo_root)?;
     let prefix = paths::clones_prefix(&repo_root)?;
 
-    if args.porcelain {
-        let branch = targets::current_branch(&repo_root).unwrap_or_else(|| "(detached)".to_string());
-        let status = targets::status(&repo_root);
-        println!("{}\t{}\t{}\t{}", repo_root.display(), "main", branch, status);
+    // Backfilling writes metadata through git, which --no-status avoids.
+    if !args.no_status {
+        meta::backfill_ids(&repo_root, &clones_dir, &prefix)?;
+    }
+    let mut entries = paths::list_clone_dirs(&clones_dir, &prefix)?;
+    entries.sort();
+    let mut checkouts = vec![(repo_root.clone(), "main".to_string(), true)];
+    for path in entries {
+        let name = targets::space_name(&path, &prefix);
+        checkouts.push((path, name, false));
+    }
+    let jobs = match config::cfg_default("spaces.list.jobs", "SPACES_LIST_JOBS", "", None, &repo_root)?.as_str() {
+        "" => parallel::default_jobs(),
+        value => value.parse().map_err(|_| anyhow::anyhow!("Invalid spaces.list.jobs: {value}"))?,
+    };
+    let branch_pattern = match &args.branch {
+        Some(glob) => Some(glob::Pattern::new(glob).with_context(|| format!("Invalid branch pattern: {glob}"))?),
+        None => None,
+    };
+    let cutoff = match &args.older_than {
+        Some(value) => Some(meta::now().saturating_sub(parse_duration(value)?)),
+        None => None,
+    };
 
-        if clones_dir.is_dir() {
-            let mut entries: Vec<PathBuf> = std::fs::read_dir(&clones_dir)?
-                .filter_map(|entry| entry.ok().map(|e| e.path()))
-                .filter(|p| p.is_dir())
-                .filter(|p| p.file_name().and_then(|s| s.to_str()).map(|n| n.starts_with(&prefix)).unwrap_or(false))
-                .collect();
-            entries.sort();
+    let with_status = !args.no_status;
+    let mut spaces = parallel::map(&checkouts, jobs, |(path, name, main)| {
+        info::collect(path, name, *main, with_status)
+    });
+    // --merged and --older-than pick spaces to clean up, so the main repo
+    // never matches them.
+    spaces.retain(|space| {
+        (!args.dirty || space.changes.as_ref().is_some_and(|c| !c.is_empty()))
+            && (!args.clean || (space.status != "missing" && space.changes.as_ref().is_some_and(targets::Changes::is_empty)))
+            && branch_pattern
+                .as_ref()
+                .is_none_or(|pattern| space.branch.as_deref().is_some_and(|b| pattern.matches(b)))
+            && (!args.merged || (!space.main && space.is_merged()))
+            && cutoff.is_none_or(|cutoff| !space.main && space.age_timestamp().is_some_and(|t| t < cutoff))
+    });
+
+    if args.sort == cli::ListSort::Size {
+        let sizes = parallel::map(&spaces, jobs, |space| {
+            (!space.main).then(|| du::dir_size(&space.path, true) + du::git_dir_size(&space.path))
+        });
+        for (space, size) in spaces.iter_mut().zip(sizes) {
+            space.size = size;
+        }
+    }
+    // The main repo stays on top; spaces follow in --sort order, with
+    // unknown values last and ties broken by name.
+    let first = spaces.iter().take_while(|space| space.main).count();
+    spaces[first..].sort_by(|a, b| {
+        let order = match args.sort {
+            cli::ListSort::Name => std::cmp::Ordering::Equal,
+            cli::ListSort::Age => oldest_first(a.age_timestamp(), b.age_timestamp()),
+            cli::ListSort::LastUsed => oldest_first(a.last_used, b.last_used),
+            cli::ListSort::Size => b.size.cmp(&a.size),
+        };
+        order.then_with(|| a.name.cmp(&b.name))
+    });
+
+    if args.json {
+        let output = info::ListOutput {
+            version: info::SCHEMA_VERSION,
+            spaces,
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
 
-            for path in entries {
-                let branch = targets::current_branch(&path).unwrap_or_else(|| "(detached)".to_string());
-                let status = targets::status(&path);
-                let name = targets::space_name(&path, &prefix);
-                println!("{}\t{}\t{}\t{}", path.display(), name, branch, status);
-            }
+    if let Some(format) = args.porcelain {
+        for space in &spaces {
+            println!("{}", porcelain_line(space, format));
         }
         return Ok(());
     }
 
     println!("Spaces");
     println!();
-    println!("{:<24} {:<24} {}", "SPACE", "BRANCH", "PATH");
-    println!("{:<24} {:<24} {}", "-----", "------", "----");
+    println!(
+        "{:<4} {:<24} {:<24} {:<16} {:<10} {:<15} {:<4} PATH",
+        "ID", "SPACE", "BRANCH", "BASE", "SYNC", "KIND", "LOCK"
+    );
+    println!(
+        "{:<4} {:<24} {:<24} {:<16} {:<10} {:<15} {:<4} ----",
+        "--", "-----", "------", "----", "----", "----", "----"
+    );
 
-    let branch = targets::current_branch(&repo_root).unwrap_or_else(|| "(detached)".to_string());
-    println!("{:<24} {:<24} {}", "main", branch, repo_root.display());
+    for space in &spaces {
+        let mut base = space.base_ref.clone().unwrap_or_else(|| "-".to_string());
+        if let Some(count) = space.base_ahead.filter(|count| *count > 0) {
+            base.push_str(&format!(" +{count}"));
+        }
+        let mut kind = space.kind.unwrap_or("-").to_string();
+        if space.sparse {
+            kind.push_str(",sparse");
+        }
+        println!(
+            "{:<4} {:<24} {:<24} {:<16} {:<10} {:<15} {:<4} {}",
+            space.id.map(|id| id.to_string()).unwrap_or_else(|| "-".to_string()),
+            space.name,
+            space.branch.as_deref().unwrap_or("(detached)"),
+            base,
+            space.sync_label(),
+            kind,
+            if space.locked { "yes" } else { "-" },
+            space.path.display()
+        );
+    }
 
-    if clones_dir.is_dir() {
-        let mut rows = Vec::new();
-        for entry in std::fs::read_dir(&clones_dir)? {
-            let entry = entry?;
-            if !entry.file_type()?.is_dir() {
-                continue;
+    println!();
+    println!("Tip: Use 'spaces list --json' for machine-readable output");
+    Ok(())
+}
+
+fn cmd_status(args: cli::StatusArgs) -> Result<()> {
+    const UNPUSHED_SHOWN: usize = 5;
+
+    let repo_root = paths::repo_root()?;
+    let clones_dir = paths::clones_dir(&repo_root)?;
+    let prefix = paths::clones_prefix(&repo_root)?;
+
+    let mut checkouts = Vec::new();
+    if args.targets.is_empty() {
+        checkouts.push((repo_root.clone(), "main".to_string(), true));
+        let mut entries = paths::list_clone_dirs(&clones_dir, &prefix)?;
+        entries.sort();
+        for path in entries {
+            let name = targets::space_name(&path, &prefix);
+            checkouts.push((path, name, false));
+        }
+    } else {
+        for identifier in &args.targets {
+            let target = targets::resolve_target(identifier, &repo_root, &clones_dir, &prefix)?;
+            checkouts.push((target.path, target.name, target.is_main));
+        }
+    }
+
+    let reports = parallel::map(&checkouts, parallel::default_jobs(), |(path, name, main)| {
+        info::report(path, name, *main, UNPUSHED_SHOWN)
+    });
+
+    let now = meta::now();
+    let mut attention = Vec::new();
+    for report in &reports {
+        if report.needs_attention() {
+            attention.push(report.name.clone());
+        } else if args.attention {
+            continue;
+        }
+
+        let marker = if report.needs_attention() { "[!]" } else { "[OK]" };
+        let id = report.id.map(|id| format!(" #{id}")).unwrap_or_default();
+        println!("{marker} {}{id}  {}", report.name, report.path.display());
+        if report.status.state == "missing" {
+            println!("    missing");
+            println!();
+            continue;
+        }
+
+        let status = &report.status;
+        let mut branch = status.branch.clone().unwrap_or_else(|| "(detached)".to_string());
+        if let Some(upstream) = &status.upstream {
+            branch.push_str(&format!(" -> {upstream}"));
+            if let (Some(ahead), Some(behind)) = (status.ahead, status.behind) {
+                branch.push_str(&format!(" (+{ahead}/-{behind})"));
             }
-            let path = entry.path();
-            if let Some(name) = path.file_name().and_then(|s| s.to_str()) {
-                if !name.starts_with(&prefix) {
-                    continue;
-                }
+        } else if status.pushed == Some(false) {
+            branch.push_str(" (never pushed)");
+        }
+        if let Some(base) = &report.base_ref {
+            branch.push_str(&format!(", base {base} +{}", status.base_ahead.unwrap_or(0)));
+        }
+        println!("    branch:      {branch}");
+
+        let changes = &status.changes;
+        if changes.is_empty() {
+            println!("    changes:     clean");
+        } else {
+            let mut parts = vec![
+                format!("{} staged", changes.staged),
+                format!("{} unstaged", changes.unstaged),
+                format!("{} untracked", changes.untracked),
+            ];
+            if changes.conflicted > 0 {
+                parts.push(format!("{} conflicted", changes.conflicted));
+            }
+            println!("    changes:     {}", parts.join(", "));
+        }
+        if report.stashes > 0 {
+            println!("    stashes:     {}", report.stashes);
+        }
+        if let Some(operation) = report.operation {
+            println!("    in progress: {operation}");
+        }
+        if let Some(commit) = &report.last_commit {
+            let age = ui::format_age(now.saturating_sub(commit.timestamp));
+            println!("    last commit: {age} ago, {}", commit.subject);
+        }
+        if report.unpushed > 0 {
+            println!("    unpushed:    {} commit(s)", report.unpushed);
+            for commit in &report.unpushed_commits {
+                println!("                 {commit}");
+            }
+            if report.unpushed as usize > report.unpushed_commits.len() {
+                println!("                 ...");
+            }
+        }
+        println!();
+    }
+
+    if attention.is_empty() {
+        ui::log_info("Nothing needs attention");
+    } else {
+        ui::log_warn(&format!(
+            "{} of {} need attention: {}",
+            attention.len(),
+            reports.len(),
+            attention.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+fn cmd_du(args: cli::DuArgs) -> Result<()> {
+    let repo_root = paths::repo_root()?;
+    let clones_dir = paths::clones_dir(&repo_root)?;
+    let prefix = paths::clones_prefix(&repo_root)?;
+    let mirror_dir = paths::mirror_dir(&repo_root)?;
+    let jobs = parallel::default_jobs();
+
+    ui::log_step("Measuring spaces...");
+    let mut entries = paths::list_clone_dirs(&clones_dir, &prefix)?;
+    entries.sort();
+    struct Usage {
+        name: String,
+        path: PathBuf,
+        worktree: bool,
+        files: u64,
+        git: u64,
+        shared: Vec<PathBuf>,
+        ignored: Vec<(String, u64)>,
+    }
+    let mut usages = parallel::map(&entries, jobs, |path| Usage {
+        name: targets::display_name(path, &prefix),
+        path: path.clone(),
+        worktree: clone::Backend::of_space(path) == clone::Backend::Worktree,
+        files: du::dir_size(path, true),
+        git: du::git_dir_size(path),
+        shared: du::shared_object_dirs(path),
+        ignored: if args.ignored { du::ignored_entries(path) } else { Vec::new() },
+    });
+
+    // Several spaces usually borrow from the same store; measure each once.
+    let mut shared_dirs: Vec<PathBuf> = Vec::new();
+    for usage in &usages {
+        for dir in &usage.shared {
+            if !shared_dirs.contains(dir) {
+                shared_dirs.push(dir.clone());
             }
-            let branch = targets::current_branch(&path).unwrap_or_else(|| "(detached)".to_string());
-            let name = targets::space_name(&path, &prefix);
-            rows.push((name, branch, path));
         }
-        rows.sort_by(|a, b| a.0.cmp(&b.0));
-        for (name, branch, path) in rows {
-            println!("{:<24} {:<24} {}", name, branch, path.display());
+    }
+    let shared_sizes: HashMap<PathBuf, u64> = shared_dirs
+        .iter()
+        .cloned()
+        .zip(parallel::map(&shared_dirs, jobs, |dir| du::dir_size(dir, false)))
+        .collect();
+    let saved_by = |usage: &Usage| -> u64 { usage.shared.iter().filter_map(|dir| shared_sizes.get(dir)).sum() };
+
+    usages.sort_by(|a, b| (b.files + b.git).cmp(&(a.files + a.git)).then_with(|| a.name.cmp(&b.name)));
+    println!("{:<24} {:>10} {:>10} {:>10} PATH", "SPACE", "FILES", "GIT", "SHARED");
+    println!("{:<24} {:>10} {:>10} {:>10} ----", "-----", "-----", "---", "------");
+    let mut files_total = 0;
+    let mut git_total = 0;
+    let mut worktree_git_total = 0;
+    let mut saved_total = 0;
+    for usage in &usages {
+        let saved = saved_by(usage);
+        println!(
+            "{:<24} {:>10} {:>10} {:>10} {}",
+            usage.name,
+            ui::format_size(usage.files),
+            ui::format_size(usage.git),
+            ui::format_size(saved),
+            usage.path.display()
+        );
+        files_total += usage.files;
+        git_total += usage.git;
+        if usage.worktree {
+            worktree_git_total += usage.git;
         }
+        saved_total += saved;
     }
 
     println!();
-    println!("Tip: Use 'spaces list --porcelain' for machine-readable output");
+    let mirror_size = if mirror_dir.exists() { du::dir_size(&mirror_dir, false) } else { 0 };
+    println!("Mirror: {} ({})", ui::format_size(mirror_size), mirror_dir.display());
+    let submodule_mirrors = submodules::mirrors_root(&mirror_dir);
+    let submodule_size = if submodule_mirrors.is_dir() { du::dir_size(&submodule_mirrors, false) } else { 0 };
+    if submodule_size > 0 {
+        println!("Submodule mirrors: {} ({})", ui::format_size(submodule_size), submodule_mirrors.display());
+    }
+
+    // Worktree git dirs live inside the mirror, so they are already part of
+    // its size.
+    let total = files_total + git_total + mirror_size + submodule_size - worktree_git_total;
+    println!(
+        "Total: {} across {} space(s) and mirrors; sharing objects saves {}",
+        ui::format_size(total),
+        usages.len(),
+        ui::format_size(saved_total)
+    );
+
+    if args.ignored {
+        println!();
+        println!("Largest ignored paths");
+        let mut by_path: HashMap<String, (u64, usize)> = HashMap::new();
+        for usage in &usages {
+            let shown: Vec<_> = usage.ignored.iter().filter(|(_, size)| *size > 0).take(args.top).collect();
+            if !shown.is_empty() {
+                println!();
+                println!("{}:", usage.name);
+                for (rel, size) in shown {
+                    println!("  {:>10}  {rel}", ui::format_size(*size));
+                }
+            }
+            for (rel, size) in &usage.ignored {
+                let entry = by_path.entry(rel.clone()).or_insert((0, 0));
+                entry.0 += size;
+                entry.1 += 1;
+            }
+        }
+        let mut totals: Vec<_> = by_path.into_iter().filter(|(_, (size, _))| *size > 0).collect();
+        totals.sort_by(|a, b| b.1.0.cmp(&a.1.0).then_with(|| a.0.cmp(&b.0)));
+        if !totals.is_empty() {
+            println!();
+            println!("All spaces:");
+            for (rel, (size, count)) in totals.into_iter().take(args.top) {
+                println!("  {:>10}  {rel} ({count} space(s))", ui::format_size(size));
+            }
+        }
+    }
     Ok(())
 }
 
@@ -277,7 +1386,7 @@ fn cmd_copy(args: CopyArgs) -> Result<()> {
     let clones_dir = paths::clones_dir(&repo_root)?;
     let prefix = paths::clones_prefix(&repo_root)?;
 
-    let source = args.from.unwrap_or_else(|| "1".to_string());
+    let source = args.from.unwrap_or_else(|| "#1".to_string());
     let src_target = targets::resolve_target(&source, &repo_root, &clones_dir, &prefix)?;
 
     let mut patterns = args.patterns.clone();
@@ -342,6 +1451,9 @@ fn cmd_clean(args: CleanArgs) -> Result<()> {
             if entry.path() == clones_dir {
                 continue;
             }
+            if entry.file_name().to_string_lossy().starts_with(paths::INTERNAL_DIR_PREFIX) {
+                continue;
+            }
             if entry.file_type()?.is_dir() {
                 let path = entry.path();
                 if path.read_dir()?.next().is_none() {
@@ -360,14 +1472,14 @@ fn cmd_clean(args: CleanArgs) -> Result<()> {
         bail!("GitHub CLI (gh) not found or not authenticated");
     }
 
-    let main_branch = targets::current_branch(&repo_root).unwrap_or_else(|| "".to_string());
-    let clone_dirs = list_clone_dirs(&clones_dir, &prefix)?;
+    let main_branch = targets::current_branch(&repo_root).unwrap_or_default();
+    let clone_dirs = paths::list_clone_dirs(&clones_dir, &prefix)?;
     let mut removed = 0;
     let mut skipped = 0;
 
     for path in clone_dirs {
         let branch = targets::current_branch(&path).unwrap_or_else(|| "(detached)".to_string());
-        let name = targets::space_name(&path, &prefix);
+        let name = targets::display_name(&path, &prefix);
         if branch == "(detached)" || branch.is_empty() {
             skipped += 1;
             continue;
@@ -376,6 +1488,14 @@ fn cmd_clean(args: CleanArgs) -> Result<()> {
             continue;
         }
 
+        if let Some(reason) = meta::read(&path).and_then(|m| m.lock)
+            && !args.force
+        {
+            ui::log_warn(&format!("Skipping locked space: {name}{}", reason_suffix(&reason)));
+            skipped += 1;
+            continue;
+        }
+
         let dirty = git::git_stdout_opt(["status", "--porcelain"], Some(&path))
             .map(|out| !out.trim().is_empty())
             .unwrap_or(false);
@@ -404,7 +1524,7 @@ fn cmd_clean(args: CleanArgs) -> Result<()> {
                 ui::log_info(&format!("[dry-run] Would remove: {name} ({})", path.display()));
                 removed += 1;
             } else if args.yes || ui::prompt_yes_no(&format!("Remove space '{name}'?"), false)? {
-                safe_remove_clone(&path, &clones_dir)?;
+                safe_remove_clone(&path, &clones_dir, false)?;
                 removed += 1;
             } else {
                 skipped += 1;
@@ -445,6 +1565,69 @@ fn cmd_doctor() -> Result<()> {
     let default_branch = paths::default_branch(&repo_root)?;
     println!("[OK] Default branch: {default_branch}");
 
+    match clone::Backend::from_config(&repo_root) {
+        Ok(backend) => println!("[OK] Backend: {}", backend.as_str()),
+        Err(err) => println!("[x] Backend: {err}"),
+    }
+
+    let prefix = paths::clones_prefix(&repo_root)?;
+    let mut clones = 0;
+    let mut worktrees = 0;
+    for path in paths::list_clone_dirs(&clones_dir, &prefix)? {
+        match clone::Backend::of_space(&path) {
+            clone::Backend::Clone => {
+                clones += 1;
+                for (_, dir) in clone::alternates(&path) {
+                    if !dir.exists() {
+                        println!(
+                            "[x] Space {} borrows objects from missing {}",
+                            path.display(),
+                            dir.display()
+                        );
+                    }
+                }
+            }
+            clone::Backend::Worktree => {
+                worktrees += 1;
+                if !paths::git_dir(&path).is_some_and(|dir| dir.exists()) {
+                    println!("[x] Broken worktree space: {}", path.display());
+                }
+            }
+        }
+    }
+    println!("[OK] Spaces: {clones} clone(s), {worktrees} worktree(s)");
+
+    if lfs::uses_lfs(&repo_root) {
+        match lfs::version() {
+            Some(version) => println!("[OK] Git LFS: {version}"),
+            None => println!("[x] Git LFS: repository uses LFS but git-lfs is not installed"),
+        }
+        let storage = lfs::storage_dir(&mirror_dir);
+        println!(
+            "[OK] LFS store: {} ({})",
+            storage.display(),
+            if storage.exists() { "present" } else { "missing" }
+        );
+        for path in paths::list_clone_dirs(&clones_dir, &prefix)? {
+            if clone::Backend::of_space(&path) == clone::Backend::Clone
+                && lfs::space_storage(&path).as_deref() != Some(storage.as_path())
+            {
+                println!("[!] Space does not share the LFS store: {}", path.display());
+            }
+        }
+    } else {
+        println!("[OK] Git LFS: not used");
+    }
+
+    if mirror_dir.exists() {
+        let prunable = git::git_stdout_opt(["worktree", "list", "--porcelain"], Some(&mirror_dir))
+            .map(|out| out.lines().filter(|line| line.starts_with("prunable")).count())
+            .unwrap_or(0);
+        if prunable > 0 {
+            println!("[!] Stale mirror worktrees: {prunable} (run 'git worktree prune' in the mirror)");
+        }
+    }
+
     Ok(())
 }
 
@@ -453,10 +1636,18 @@ fn cmd_mirrors(args: cli::MirrorsArgs) -> Result<()> {
     let mirror_dir = paths::mirror_dir(&repo_root)?;
 
     match args.command {
-        Some(cli::MirrorsCommand::Update) => {
+        Some(cli::MirrorsCommand::Update { lfs }) => {
             mirror::ensure_mirror(&repo_root, &mirror_dir)?;
             mirror::update_mirror(&repo_root, &mirror_dir)?;
             println!("updated: {}", mirror_dir.display());
+            let prefetch = lfs
+                || config::is_truthy(&config::cfg_default("spaces.lfs.prefetch", "", "false", None, &repo_root)?);
+            if prefetch && lfs::uses_lfs(&repo_root) && lfs::prefetch(&mirror_dir, &paths::default_branch(&repo_root)?)? {
+                println!("prefetched: {}", lfs::storage_dir(&mirror_dir).display());
+            }
+            for path in submodules::update_mirrors(&mirror_dir)? {
+                println!("updated: {}", path.display());
+            }
         }
         None => {
             println!("{}", mirror_dir.display());
@@ -470,6 +1661,138 @@ fn cmd_mirrors(args: cli::MirrorsArgs) -> Result<()> {
     Ok(())
 }
 
+fn cmd_detach(args: cli::DetachArgs) -> Result<()> {
+    let repo_root = paths::repo_root()?;
+    let clones_dir = paths::clones_dir(&repo_root)?;
+    let prefix = paths::clones_prefix(&repo_root)?;
+
+    let targets = if args.all {
+        list_space_names(&clones_dir, &prefix)?
+    } else {
+        args.targets
+    };
+    if targets.is_empty() {
+        bail!("Usage: spaces detach <space|id>... | --all");
+    }
+
+    for identifier in targets {
+        let target = targets::resolve_target(&identifier, &repo_root, &clones_dir, &prefix)?;
+        if target.is_main {
+            ui::log_warn("Main repository has no mirror alternates; skipping");
+            continue;
+        }
+        if clone::Backend::of_space(&target.path) == clone::Backend::Worktree {
+            ui::log_warn(&format!("{} is a worktree of the mirror and cannot be detached", target.name));
+            continue;
+        }
+        ui::log_step(&format!("Detaching: {}", target.name));
+        if clone::dissociate(&target.path)? {
+            ui::log_info(&format!("Detached from mirror: {}", target.path.display()));
+        } else {
+            ui::log_info(&format!("Already self-contained: {}", target.path.display()));
+        }
+    }
+    Ok(())
+}
+
+fn cmd_pool(args: cli::PoolArgs) -> Result<()> {
+    let repo_root = paths::repo_root()?;
+    let clones_dir = paths::clones_dir(&repo_root)?;
+    let mirror_dir = paths::mirror_dir(&repo_root)?;
+    let pool_dir = pool::pool_dir(&clones_dir);
+
+    match args.command {
+        Some(cli::PoolCommand::Fill { size, background }) => {
+            if clone::Backend::from_config(&repo_root)? != clone::Backend::Clone {
+                bail!("The clone pool requires spaces.backend = clone");
+            }
+
+            if background {
+                std::fs::create_dir_all(&pool_dir)?;
+                let log_path = pool_dir.join("fill.log");
+                let log = std::fs::File::create(&log_path)?;
+                let mut command = std::process::Command::new(std::env::current_exe()?);
+                command
+                    .args(["pool", "fill", "--size", &size.to_string()])
+                    .current_dir(&repo_root)
+                    .stdin(std::process::Stdio::null())
+                    .stdout(log.try_clone()?)
+                    .stderr(log);
+                // Its own process group keeps the fill out of the shell's
+                // job control, so closing the terminal does not hang it up.
+                #[cfg(unix)]
+                std::os::unix::process::CommandExt::process_group(&mut command, 0);
+                command.spawn()?;
+                ui::log_info(&format!("Filling pool in the background (log: {})", log_path.display()));
+                return Ok(());
+            }
+
+            let ready = pool::ready_entries(&clones_dir)?.len();
+            if ready >= size {
+                ui::log_info(&format!("Pool already holds {ready} clone(s)"));
+                return Ok(());
+            }
+
+            mirror::ensure_mirror(&repo_root, &mirror_dir)?;
+            mirror::update_mirror(&repo_root, &mirror_dir)?;
+            let base_ref = paths::default_branch(&repo_root)?;
+
+            rollback::install_interrupt_handler();
+            for index in 0..size - ready {
+                let (building, entry) = pool::entry_paths(&clones_dir, index);
+                ui::log_step(&format!("Preparing pool clone {}/{}", index + 1, size - ready));
+                let guard = rollback::SpaceGuard::new(&building, &mirror_dir, false);
+                let plan = clone::ClonePlan {
+                    path: building.clone(),
+                    backend: clone::Backend::Clone,
+                    branch: None,
+                    base_ref: base_ref.clone(),
+                    pull_request: None,
+                    from_space: None,
+                    detach: false,
+                    sparse: Vec::new(),
+                    depth: None,
+                    shallow_since: None,
+                    filter: None,
+                    lfs_storage: lfs_storage(&repo_root, &mirror_dir),
+                    dissociate: false,
+                };
+                clone::create_clone(&repo_root, &mirror_dir, &plan)?;
+                rollback::check_interrupted()?;
+                submodules::init(&building, &mirror_dir, true, false)?;
+                rollback::check_interrupted()?;
+
+                // Copying and postCreate (dependency installs and the like)
+                // are what makes a new space slow, so they run now.
+                // SPACES_POOL tells hooks the space has no name yet.
+                copy_into_space(&repo_root, &building, None)?;
+                rollback::check_interrupted()?;
+                let mut envs = HashMap::new();
+                envs.insert("REPO_ROOT".to_string(), repo_root.to_string_lossy().to_string());
+                envs.insert("CLONE_PATH".to_string(), building.to_string_lossy().to_string());
+                envs.insert("SPACES_POOL".to_string(), "1".to_string());
+                hooks::run_hooks("postCreate", None, &repo_root, &building, &envs)?;
+                rollback::check_interrupted()?;
+
+                pool::publish(&building, &entry)?;
+                guard.commit();
+            }
+            ui::log_info(&format!("Pool holds {size} clone(s)"));
+        }
+        Some(cli::PoolCommand::Clear) => {
+            if pool_dir.exists() {
+                std::fs::remove_dir_all(&pool_dir)?;
+            }
+            ui::log_info("Pool cleared");
+        }
+        None => {
+            println!("{}", pool_dir.display());
+            println!("ready: {}", pool::ready_entries(&clones_dir)?.len());
+        }
+    }
+    Ok(())
+}
+
 fn cmd_config(args: cli::ConfigArgs) -> Result<()> {
     use config::Scope;
 
@@ -480,8 +1803,7 @@ fn cmd_config(args: cli::ConfigArgs) -> Result<()> {
     let mut value: Option<String> = None;
     let mut extras = Vec::new();
 
-    let mut iter = args.args.into_iter();
-    while let Some(arg) = iter.next() {
+    for arg in args.args {
         match arg.as_str() {
             "--global" | "global" => scope = Scope::Global,
             "--local" | "local" => scope = Scope::Local,
@@ -566,41 +1888,117 @@ fn cmd_config(args: cli::ConfigArgs) -> Result<()> {
     Ok(())
 }
 
-fn safe_remove_clone(path: &Path, clones_dir: &Path) -> Result<()> {
+/// Removes a space. `force` lets worktree spaces go with local changes;
+/// clones are removed either way.
+fn safe_remove_clone(path: &Path, clones_dir: &Path, force: bool) -> Result<()> {
     if !path.starts_with(clones_dir) {
         bail!("Refusing to remove path outside clones dir: {}", path.display());
     }
     if !path.join(".git").exists() {
         bail!("Refusing to remove non-git directory: {}", path.display());
     }
-    std::fs::remove_dir_all(path)?;
+    match clone::Backend::of_space(path) {
+        clone::Backend::Worktree => clone::remove_worktree(path, force)?,
+        clone::Backend::Clone => std::fs::remove_dir_all(path)?,
+    }
     ui::log_info(&format!("Removed space: {}", path.display()));
     Ok(())
 }
 
-fn list_clone_dirs(clones_dir: &Path, prefix: &str) -> Result<Vec<PathBuf>> {
-    if !clones_dir.is_dir() {
-        return Ok(Vec::new());
+/// Returns the recorded metadata of a space, or metadata derived from the
+/// folder for spaces created before metadata existed.
+fn space_meta_or_default(target: &targets::Target, repo_root: &Path) -> Result<meta::SpaceMeta> {
+    match &target.meta {
+        Some(space_meta) => Ok(space_meta.clone()),
+        None => meta::derive(&target.path, &target.name, repo_root),
     }
-    let mut out = Vec::new();
-    for entry in std::fs::read_dir(clones_dir)? {
-        let entry = entry?;
-        if !entry.file_type()?.is_dir() {
-            continue;
-        }
-        let path = entry.path();
-        if let Some(name) = path.file_name().and_then(|s| s.to_str()) {
-            if !name.starts_with(prefix) {
-                continue;
-            }
-        }
-        out.push(path);
+}
+
+fn locked_message(name: &str, reason: &str) -> String {
+    format!("Space '{name}' is locked{}; use --force to override", reason_suffix(reason))
+}
+
+fn reason_suffix(reason: &str) -> String {
+    if reason.is_empty() { String::new() } else { format!(" ({reason})") }
+}
+
+/// Destructive commands only act on a prefix or fuzzy match once the user
+/// confirms it at a terminal; `--yes` does not cover a guess.
+fn confirm_inexact(target: &targets::Target, identifier: &str, action: &str) -> Result<()> {
+    if !target.inexact {
+        return Ok(());
+    }
+    let prompt = format!("'{identifier}' matched space '{}'. Really {action} it?", target.name);
+    if std::io::stdin().is_terminal() && ui::prompt_yes_no(&prompt, false)? {
+        return Ok(());
+    }
+    bail!(
+        "'{identifier}' is only a partial match for space '{}'; use its full name or id to {action} it",
+        target.name
+    )
+}
+
+/// One `list --porcelain` line. `v1` is path, name, branch and status, as
+/// it always was; `v2` appends id, lock, upstream, ahead, behind, base ahead
+/// and pushed. Unknown values are empty.
+fn porcelain_line(space: &info::SpaceInfo, format: cli::PorcelainFormat) -> String {
+    let mut fields = vec![
+        space.path.display().to_string(),
+        space.name.clone(),
+        space.branch.clone().unwrap_or_else(|| "(detached)".to_string()),
+        space.status.clone(),
+    ];
+    if format == cli::PorcelainFormat::V2 {
+        let count = |value: Option<u32>| value.map(|v| v.to_string()).unwrap_or_default();
+        let pushed = match space.pushed {
+            Some(true) => "pushed",
+            Some(false) => "unpushed",
+            None => "",
+        };
+        fields.extend([
+            count(space.id),
+            if space.locked { "locked" } else { "" }.to_string(),
+            space.upstream.clone().unwrap_or_default(),
+            count(space.ahead),
+            count(space.behind),
+            count(space.base_ahead),
+            pushed.to_string(),
+        ]);
+    }
+    fields.join("\t")
+}
+
+/// Orders timestamps oldest first, with unknown ones last.
+fn oldest_first(a: Option<u64>, b: Option<u64>) -> std::cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+}
+
+/// Parses durations like `90m`, `12h`, `30d` or `2w` into seconds.
+fn parse_duration(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => bail!("Invalid duration: {value} (expected a number with s, m, h, d or w, e.g. 30d)"),
+    };
+    match number.parse::<u64>() {
+        Ok(count) => Ok(count.saturating_mul(unit_secs)),
+        Err(_) => bail!("Invalid duration: {value} (expected a number with s, m, h, d or w, e.g. 30d)"),
     }
-    Ok(out)
 }
 
 fn list_space_names(clones_dir: &Path, prefix: &str) -> Result<Vec<String>> {
-    let dirs = list_clone_dirs(clones_dir, prefix)?;
+    let dirs = paths::list_clone_dirs(clones_dir, prefix)?;
     let mut names = Vec::new();
     for path in dirs {
         names.push(targets::space_name(&path, prefix));
@@ -630,6 +2028,35 @@ where
     }
 }
 
+fn lfs_storage(repo_root: &Path, mirror_dir: &Path) -> Option<PathBuf> {
+    if !lfs::uses_lfs(repo_root) {
+        return None;
+    }
+    if lfs::version().is_none() {
+        ui::log_warn("Repository uses Git LFS but git-lfs is not installed");
+    }
+    Some(lfs::storage_dir(mirror_dir))
+}
+
+fn config_or(value: Option<String>, key: &str, repo_root: &Path) -> Result<Option<String>> {
+    if value.is_some() {
+        return Ok(value);
+    }
+    let configured = config::cfg_default(key, "", "", None, repo_root)?;
+    Ok(if configured.is_empty() { None } else { Some(configured) })
+}
+
+fn pr_space_name(number: u32, repo_root: &Path) -> String {
+    let title = gh_stdout_opt(
+        ["pr", "view", &number.to_string(), "--json", "title", "--jq", ".title"],
+        Some(repo_root),
+    );
+    match title.map(|t| paths::slugify(&t, 40)) {
+        Some(slug) if !slug.is_empty() => format!("pr-{number}-{slug}"),
+        _ => format!("pr-{number}"),
+    }
+}
+
 fn dedupe(items: Vec<String>) -> Vec<String> {
     let mut seen = std::collections::HashSet::new();
     let mut out = Vec::new();
@@ -640,3 +2067,31 @@ fn dedupe(items: Vec<String>) -> Vec<String> {
     }
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("45s").unwrap(), 45);
+        assert_eq!(parse_duration("90m").unwrap(), 90 * 60);
+        assert_eq!(parse_duration("12h").unwrap(), 12 * 60 * 60);
+        assert_eq!(parse_duration(" 30d ").unwrap(), 30 * 24 * 60 * 60);
+        assert_eq!(parse_duration("2w").unwrap(), 2 * 7 * 24 * 60 * 60);
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid_input() {
+        for value in ["", "30", "d", "3x", "-3d", "1.5h", "3dd"] {
+            assert!(parse_duration(value).is_err(), "accepted {value:?}");
+        }
+    }
+
+    #[test]
+    fn oldest_first_puts_unknown_last() {
+        let mut stamps = vec![None, Some(30), Some(10), None, Some(20)];
+        stamps.sort_by(|a, b| oldest_first(*a, *b));
+        assert_eq!(stamps, vec![Some(10), Some(20), Some(30), None, None]);
+    }
+}
@@ -0,0 +1,386 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use crate::git;
+use crate::paths;
+
+const META_FILE: &str = "spaces-meta";
+
+#[derive(Clone)]
+pub struct SpaceMeta {
+    pub id: Option<u32>,
+    pub name: String,
+    pub folder: String,
+    pub branch: Option<String>,
+    pub base_ref: String,
+    pub created_at: u64,
+    pub version: String,
+    pub mirror_head: Option<String>,
+    pub profile: Option<String>,
+    /// `Some(reason)` when the space is locked against removal; the reason
+    /// may be empty.
+    pub lock: Option<String>,
+}
+
+pub fn meta_path(space_path: &Path) -> Option<PathBuf> {
+    paths::git_dir(space_path).map(|dir| dir.join(META_FILE))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn read(space_path: &Path) -> Option<SpaceMeta> {
+    read_file(&meta_path(space_path)?)
+}
+
+/// Reads the `space.*` keys of a metadata file. Archives keep a copy of the
+/// metadata outside the space, so this also works on those. The file is
+/// parsed here rather than through `git config`, because `list` reads it
+/// for every space and `--no-status` must not start git at all.
+pub fn read_file(file: &Path) -> Option<SpaceMeta> {
+    let text = std::fs::read_to_string(file).ok()?;
+
+    let mut meta = SpaceMeta {
+        id: None,
+        name: String::new(),
+        folder: String::new(),
+        branch: None,
+        base_ref: String::new(),
+        created_at: 0,
+        version: String::new(),
+        mirror_head: None,
+        profile: None,
+        lock: None,
+    };
+    let mut locked = false;
+    let mut lock_reason = String::new();
+    for (key, value) in parse_config(&text) {
+        // git variable names are case-insensitive
+        match key.as_str() {
+            "space.id" => meta.id = value.parse().ok(),
+            "space.name" => meta.name = value,
+            "space.folder" => meta.folder = value,
+            "space.branch" => meta.branch = Some(value),
+            "space.baseref" => meta.base_ref = value,
+            "space.createdat" => meta.created_at = value.parse().unwrap_or(0),
+            "space.version" => meta.version = value,
+            "space.mirrorhead" => meta.mirror_head = Some(value),
+            "space.profile" => meta.profile = Some(value),
+            "space.locked" => locked = crate::config::is_truthy(&value),
+            "space.lockreason" => lock_reason = value,
+            _ => {}
+        }
+    }
+    if locked {
+        meta.lock = Some(lock_reason);
+    }
+
+    if meta.name.is_empty() {
+        return None;
+    }
+    Some(meta)
+}
+
+/// Parses the subset of the git config format that `git config -f` writes:
+/// `[section]` headers, `key = value` lines, comments, and values that are
+/// quoted or use `\"`, `\\`, `\n` and `\t` escapes. Returns lowercased
+/// `section.key` names with their values, in file order.
+fn parse_config(text: &str) -> Vec<(String, String)> {
+    let mut entries = Vec::new();
+    let mut section = String::new();
+    for line in text.lines() {
+        let line = line.trim_start();
+        if let Some(header) = line.strip_prefix('[') {
+            section = header.split(']').next().unwrap_or_default().trim().to_ascii_lowercase();
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let (key, raw) = match line.split_once('=') {
+            Some((key, raw)) => (key.trim(), raw),
+            // A bare key is boolean true.
+            None => (line.trim(), "true"),
+        };
+        entries.push((format!("{section}.{}", key.to_ascii_lowercase()), unquote(raw)));
+    }
+    entries
+}
+
+fn unquote(raw: &str) -> String {
+    let mut value = String::new();
+    // Unquoted whitespace is trimmed at both ends; `pending` holds spaces
+    // that only count if something follows them.
+    let mut pending = String::new();
+    let mut quoted = false;
+    let mut chars = raw.trim_start().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                value.push_str(&pending);
+                pending.clear();
+                quoted = !quoted;
+            }
+            '\\' => {
+                value.push_str(&pending);
+                pending.clear();
+                match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('b') => {
+                        value.pop();
+                    }
+                    Some(other) => value.push(other),
+                    None => {}
+                }
+            }
+            '#' | ';' if !quoted => break,
+            c if c.is_whitespace() && !quoted => pending.push(c),
+            c => {
+                value.push_str(&pending);
+                pending.clear();
+                value.push(c);
+            }
+        }
+    }
+    value
+}
+
+pub fn write(space_path: &Path, meta: &SpaceMeta) -> Result<()> {
+    let file = meta_path(space_path)
+        .with_context(|| format!("no git dir for space {}", space_path.display()))?;
+    write_file(&file, meta)
+}
+
+pub fn write_file(file: &Path, meta: &SpaceMeta) -> Result<()> {
+    let file_str = file.to_string_lossy().to_string();
+
+    let mut entries = vec![
+        ("space.name", meta.name.clone()),
+        ("space.folder", meta.folder.clone()),
+        ("space.baseRef", meta.base_ref.clone()),
+        ("space.createdAt", meta.created_at.to_string()),
+        ("space.version", meta.version.clone()),
+    ];
+    if let Some(id) = meta.id {
+        entries.push(("space.id", id.to_string()));
+    }
+    if let Some(branch) = &meta.branch {
+        entries.push(("space.branch", branch.clone()));
+    }
+    if let Some(head) = &meta.mirror_head {
+        entries.push(("space.mirrorHead", head.clone()));
+    }
+    if let Some(profile) = &meta.profile {
+        entries.push(("space.profile", profile.clone()));
+    }
+    if let Some(reason) = &meta.lock {
+        entries.push(("space.locked", "true".to_string()));
+        if !reason.is_empty() {
+            entries.push(("space.lockReason", reason.clone()));
+        }
+    }
+
+    for (key, value) in entries {
+        git::git_check(["config", "-f", &file_str, key, &value], None)?;
+    }
+    Ok(())
+}
+
+/// Locks (`Some(reason)`) or unlocks (`None`) a space. `write` never drops
+/// keys, so this unsets the old lock keys first.
+pub fn set_lock(space_path: &Path, reason: Option<&str>) -> Result<()> {
+    let file = meta_path(space_path)
+        .with_context(|| format!("no git dir for space {}", space_path.display()))?;
+    let file_str = file.to_string_lossy().to_string();
+    for key in ["space.locked", "space.lockReason"] {
+        let _ = git::git_check(["config", "-f", &file_str, "--unset", key], None);
+    }
+    if let Some(reason) = reason {
+        git::git_check(["config", "-f", &file_str, "space.locked", "true"], None)?;
+        if !reason.is_empty() {
+            git::git_check(["config", "-f", &file_str, "space.lockReason", reason], None)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hands out the next space id. Id 1 is reserved for the main repo.
+pub fn allocate_id(repo_root: &Path, clones_dir: &Path, prefix: &str) -> Result<u32> {
+    let state = paths::state_file(repo_root)?;
+    let state_str = state.to_string_lossy().to_string();
+
+    let mut next = git::git_stdout_opt(["config", "-f", &state_str, "--get", "state.nextId"], None)
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(2)
+        .max(2);
+    for path in paths::list_clone_dirs(clones_dir, prefix)? {
+        if let Some(id) = read(&path).and_then(|meta| meta.id) {
+            next = next.max(id + 1);
+        }
+    }
+
+    git::git_check(["config", "-f", &state_str, "state.nextId", &(next + 1).to_string()], None)?;
+    Ok(next)
+}
+
+/// Metadata for a space created before metadata existed, derived from its
+/// folder and checkout.
+pub fn derive(space_path: &Path, name: &str, repo_root: &Path) -> Result<SpaceMeta> {
+    Ok(SpaceMeta {
+        id: None,
+        name: name.to_string(),
+        folder: space_path
+            .file_name()
+            .map(|folder| folder.to_string_lossy().to_string())
+            .unwrap_or_else(|| name.to_string()),
+        branch: git::git_stdout_opt(["branch", "--show-current"], Some(space_path)),
+        base_ref: paths::default_branch(repo_root)?,
+        created_at: 0,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        mirror_head: None,
+        profile: None,
+        lock: None,
+    })
+}
+
+/// Gives every space without an id one, in folder order. Spaces created
+/// before ids (or metadata) existed get theirs the first time spaces are
+/// listed or looked up by id; afterwards this only reads.
+pub fn backfill_ids(repo_root: &Path, clones_dir: &Path, prefix: &str) -> Result<()> {
+    let mut dirs = paths::list_clone_dirs(clones_dir, prefix)?;
+    dirs.sort();
+    for path in dirs {
+        if meta_path(&path).is_none() {
+            continue;
+        }
+        let mut space_meta = match read(&path) {
+            Some(space_meta) if space_meta.id.is_some() => continue,
+            Some(space_meta) => space_meta,
+            None => {
+                let folder = path.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+                derive(&path, folder.strip_prefix(prefix).unwrap_or(&folder), repo_root)?
+            }
+        };
+        space_meta.id = Some(allocate_id(repo_root, clones_dir, prefix)?);
+        write(&path, &space_meta)?;
+    }
+    Ok(())
+}
+
+pub fn find_by_id(clones_dir: &Path, prefix: &str, id: u32) -> Option<PathBuf> {
+    let dirs = paths::list_clone_dirs(clones_dir, prefix).ok()?;
+    dirs.into_iter().find(|path| read(path).and_then(|meta| meta.id) == Some(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn sample() -> SpaceMeta {
+        SpaceMeta {
+            id: Some(7),
+            name: "feature/login".to_string(),
+            folder: "s-feature-login".to_string(),
+            branch: Some("feature/login".to_string()),
+            base_ref: "origin/main".to_string(),
+            created_at: 1_700_000_000,
+            version: "1.2.3".to_string(),
+            mirror_head: Some("0123456789abcdef".to_string()),
+            profile: Some("review".to_string()),
+            lock: Some("waiting on CI".to_string()),
+        }
+    }
+
+    fn assert_same(read: &SpaceMeta, written: &SpaceMeta) {
+        assert_eq!(read.id, written.id);
+        assert_eq!(read.name, written.name);
+        assert_eq!(read.folder, written.folder);
+        assert_eq!(read.branch, written.branch);
+        assert_eq!(read.base_ref, written.base_ref);
+        assert_eq!(read.created_at, written.created_at);
+        assert_eq!(read.version, written.version);
+        assert_eq!(read.mirror_head, written.mirror_head);
+        assert_eq!(read.profile, written.profile);
+        assert_eq!(read.lock, written.lock);
+    }
+
+    #[test]
+    fn write_then_read_round_trips_every_field() {
+        let tmp = TempDir::new("meta-full");
+        let file = tmp.path().join(META_FILE);
+        let written = sample();
+        write_file(&file, &written).unwrap();
+        assert_same(&read_file(&file).expect("metadata"), &written);
+    }
+
+    #[test]
+    fn write_then_read_keeps_optional_fields_unset() {
+        let tmp = TempDir::new("meta-minimal");
+        let file = tmp.path().join(META_FILE);
+        let written = SpaceMeta {
+            id: None,
+            branch: None,
+            mirror_head: None,
+            profile: None,
+            lock: Some(String::new()),
+            ..sample()
+        };
+        write_file(&file, &written).unwrap();
+        assert_same(&read_file(&file).expect("metadata"), &written);
+    }
+
+    #[test]
+    fn read_file_parses_values_git_quotes() {
+        let tmp = TempDir::new("meta-quoting");
+        let file = tmp.path().join(META_FILE);
+        let written = SpaceMeta {
+            name: "  padded # not a comment; \"quoted\" back\\slash\ttab  ".to_string(),
+            lock: Some("line one\nline two".to_string()),
+            ..sample()
+        };
+        write_file(&file, &written).unwrap();
+        assert_same(&read_file(&file).expect("metadata"), &written);
+    }
+
+    #[test]
+    fn parse_config_skips_comments_and_lowercases_keys() {
+        let text = "# comment\n[Space]\n\tName = demo ; trailing\n\tlocked\n[other]\n\tname = x\n";
+        assert_eq!(
+            parse_config(text),
+            vec![
+                ("space.name".to_string(), "demo".to_string()),
+                ("space.locked".to_string(), "true".to_string()),
+                ("other.name".to_string(), "x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_file_needs_a_name() {
+        let tmp = TempDir::new("meta-missing");
+        let file = tmp.path().join(META_FILE);
+        assert!(read_file(&file).is_none());
+        std::fs::write(&file, "[space]\n\tfolder = s-x\n").unwrap();
+        assert!(read_file(&file).is_none());
+    }
+
+    #[test]
+    fn set_lock_replaces_and_clears_the_reason() {
+        let tmp = TempDir::new("meta-lock");
+        std::fs::create_dir(tmp.path().join(".git")).unwrap();
+        write(tmp.path(), &sample()).unwrap();
+
+        set_lock(tmp.path(), Some("")).unwrap();
+        assert_eq!(read(tmp.path()).unwrap().lock.as_deref(), Some(""));
+        set_lock(tmp.path(), None).unwrap();
+        assert_eq!(read(tmp.path()).unwrap().lock, None);
+    }
+}
@@ -1,7 +1,8 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
 use crate::git;
 use crate::ui;
@@ -24,21 +25,194 @@ pub fn ensure_mirror(repo_root: &Path, mirror_dir: &Path) -> Result<()> {
     ], None)
 }
 
+/// Where the mirror fetches origin's branches and the main repo's local
+/// branches. `refs/heads` is only ever moved forward from these, so branches
+/// that exist just in the mirror, such as those made in worktree spaces,
+/// are never pruned or overwritten.
+const ORIGIN_REFS: &str = "refs/remotes/origin";
+const LOCAL_REFS: &str = "refs/spaces-local";
+
 pub fn update_mirror(repo_root: &Path, mirror_dir: &Path) -> Result<()> {
-    let origin_url = git::git_stdout_opt(["remote", "get-url", "origin"], Some(repo_root));
-    if let Some(url) = origin_url {
+    let mut sources = Vec::new();
+    if let Some(url) = git::git_stdout_opt(["remote", "get-url", "origin"], Some(repo_root)) {
         let _ = git::git_check(["remote", "set-url", "origin", &url], Some(mirror_dir));
-        let _ = git::git_check(["fetch", "--prune", "origin"], Some(mirror_dir));
+        sources.push((url, ORIGIN_REFS));
     }
+    sources.push((repo_root.to_string_lossy().to_string(), LOCAL_REFS));
 
-    let repo_root_str = repo_root.to_string_lossy();
-    let fetch_args = [
-        "fetch",
-        repo_root_str.as_ref(),
-        "+refs/heads/*:refs/heads/*",
-        "+refs/tags/*:refs/tags/*",
-    ];
-    let _ = git::git_check(fetch_args, Some(mirror_dir));
+    let checked_out = worktree_branches(mirror_dir);
+    for (url, namespace) in &sources {
+        let before = list_refs(mirror_dir, namespace);
+        let refspec = format!("+refs/heads/*:{namespace}/*");
+        if git::git_check(["fetch", "--prune", url.as_str(), refspec.as_str()], Some(mirror_dir)).is_err() {
+            continue;
+        }
+        let after = list_refs(mirror_dir, namespace);
+        let others: Vec<HashMap<String, String>> = sources
+            .iter()
+            .filter(|(_, other)| other != namespace)
+            .map(|(_, other)| list_refs(mirror_dir, other))
+            .collect();
+        sync_branches(mirror_dir, &before, &after, &others, &checked_out);
+    }
 
     Ok(())
 }
+
+/// Moves `refs/heads` along with one fetched namespace. A branch follows
+/// its source when the mirror has no commits of its own on it (it still
+/// points where the source did, or the source fast-forwards it), and is
+/// deleted only when no source has it and it still points where the source
+/// last had it. Branches checked out in a worktree are left alone.
+fn sync_branches(
+    mirror_dir: &Path,
+    before: &HashMap<String, String>,
+    after: &HashMap<String, String>,
+    others: &[HashMap<String, String>],
+    checked_out: &[String],
+) {
+    let heads = list_refs(mirror_dir, "refs/heads");
+    let is_ancestor = |old: &str, new: &str| {
+        git::git_check(["merge-base", "--is-ancestor", old, new], Some(mirror_dir)).is_ok()
+    };
+
+    for (branch, new) in after {
+        if checked_out.contains(branch) {
+            continue;
+        }
+        let head = format!("refs/heads/{branch}");
+        match heads.get(branch) {
+            None => {
+                let _ = git::git_check(["update-ref", head.as_str(), new.as_str(), ""], Some(mirror_dir));
+            }
+            Some(current) if current == new => {}
+            Some(current) => {
+                if before.get(branch) == Some(current) || is_ancestor(current, new) {
+                    let _ = git::git_check(["update-ref", head.as_str(), new.as_str(), current.as_str()], Some(mirror_dir));
+                }
+            }
+        }
+    }
+
+    for (branch, old) in before {
+        if after.contains_key(branch)
+            || checked_out.contains(branch)
+            || others.iter().any(|refs| refs.contains_key(branch))
+            || heads.get(branch) != Some(old)
+        {
+            continue;
+        }
+        let head = format!("refs/heads/{branch}");
+        let _ = git::git_check(["update-ref", "-d", head.as_str(), old.as_str()], Some(mirror_dir));
+    }
+}
+
+/// Branch name to commit for every ref under `namespace`.
+fn list_refs(mirror_dir: &Path, namespace: &str) -> HashMap<String, String> {
+    let prefix = format!("{namespace}/");
+    let output = git::git_stdout_opt(["for-each-ref", "--format=%(objectname) %(refname)", prefix.as_str()], Some(mirror_dir))
+        .unwrap_or_default();
+    output
+        .lines()
+        .filter_map(|line| {
+            let (sha, refname) = line.split_once(' ')?;
+            let branch = refname.strip_prefix(&prefix)?;
+            (branch != "HEAD").then(|| (branch.to_string(), sha.to_string()))
+        })
+        .collect()
+}
+
+pub fn worktree_branches(mirror_dir: &Path) -> Vec<String> {
+    let Some(output) = git::git_stdout_opt(["worktree", "list", "--porcelain"], Some(mirror_dir)) else {
+        return Vec::new();
+    };
+    output
+        .lines()
+        .filter_map(|line| line.strip_prefix("branch refs/heads/"))
+        .map(|branch| branch.to_string())
+        .collect()
+}
+
+pub fn fetch_pull_request(repo_root: &Path, mirror_dir: &Path, number: u32) -> Result<()> {
+    let Some(url) = git::git_stdout_opt(["remote", "get-url", "origin"], Some(repo_root)) else {
+        bail!("Cannot fetch PR #{number}: repository has no origin remote");
+    };
+
+    ui::log_step(&format!("Fetching PR #{number}..."));
+    let refspec = format!("+refs/pull/{number}/head:refs/pull/{number}/head");
+    git::git_check(["fetch", url.as_str(), refspec.as_str()], Some(mirror_dir))
+        .with_context(|| format!("fetch refs/pull/{number}/head"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{self, TempDir};
+
+    struct Setup {
+        _tmp: TempDir,
+        origin: std::path::PathBuf,
+        repo: std::path::PathBuf,
+        mirror: std::path::PathBuf,
+    }
+
+    fn setup(label: &str) -> Setup {
+        let tmp = TempDir::new(label);
+        let origin = tmp.path().join("origin");
+        let repo = tmp.path().join("repo");
+        let mirror = tmp.path().join("mirror");
+        testutil::init_repo(&origin);
+        testutil::git(tmp.path(), &["clone", "-q", "origin", "repo"]);
+        ensure_mirror(&repo, &mirror).unwrap();
+        update_mirror(&repo, &mirror).unwrap();
+        Setup { _tmp: tmp, origin, repo, mirror }
+    }
+
+    fn head(mirror: &Path, branch: &str) -> Option<String> {
+        git::git_stdout_opt(["rev-parse", "--verify", "--quiet", &format!("refs/heads/{branch}")], Some(mirror))
+    }
+
+    #[test]
+    fn keeps_branches_made_in_worktree_spaces() {
+        let s = setup("mirror-worktree");
+        let space = s.mirror.parent().unwrap().join("space");
+        let space_str = space.to_string_lossy().to_string();
+        testutil::git(&s.mirror, &["worktree", "add", "-q", "-b", "feat-one", &space_str, "main"]);
+        let tip = testutil::commit(&space, "unpushed");
+        testutil::git(&space, &["switch", "-q", "-c", "other"]);
+
+        update_mirror(&s.repo, &s.mirror).unwrap();
+        assert_eq!(head(&s.mirror, "feat-one"), Some(tip));
+    }
+
+    #[test]
+    fn does_not_rewind_branches_with_mirror_only_commits() {
+        let s = setup("mirror-rewind");
+        testutil::git(&s.origin, &["branch", "shared"]);
+        update_mirror(&s.repo, &s.mirror).unwrap();
+        assert!(head(&s.mirror, "shared").is_some());
+
+        let space = s.mirror.parent().unwrap().join("space");
+        let space_str = space.to_string_lossy().to_string();
+        testutil::git(&s.mirror, &["worktree", "add", "-q", &space_str, "shared"]);
+        let tip = testutil::commit(&space, "local work");
+        testutil::git(&space, &["switch", "-q", "--detach"]);
+
+        update_mirror(&s.repo, &s.mirror).unwrap();
+        assert_eq!(head(&s.mirror, "shared"), Some(tip));
+    }
+
+    #[test]
+    fn follows_and_prunes_source_branches() {
+        let s = setup("mirror-follow");
+        testutil::git(&s.origin, &["branch", "gone"]);
+        update_mirror(&s.repo, &s.mirror).unwrap();
+        assert!(head(&s.mirror, "gone").is_some());
+
+        let tip = testutil::commit(&s.origin, "moved on");
+        testutil::git(&s.origin, &["branch", "-D", "gone"]);
+        update_mirror(&s.repo, &s.mirror).unwrap();
+        assert_eq!(head(&s.mirror, "main"), Some(tip));
+        assert_eq!(head(&s.mirror, "gone"), None);
+    }
+}
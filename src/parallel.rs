@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Maps `f` over `items` on at most `jobs` threads. Results come back in the
+/// order of `items` regardless of which worker finished first.
+pub fn map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let jobs = jobs.clamp(1, items.len().max(1));
+    if jobs == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut indexed: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break;
+                        };
+                        done.push((index, f(item)));
+                    }
+                    done
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("worker thread panicked"))
+            .collect()
+    });
+    indexed.sort_by_key(|(index, _)| *index);
+    indexed.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Default worker count: one per CPU, but at least 4 because `git status`
+/// mostly waits on the disk, and at most 8 so a big machine does not start
+/// dozens of git processes at once.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(4).clamp(4, 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn map_preserves_order_when_workers_finish_out_of_order() {
+        let items: Vec<u64> = (0..16).collect();
+        let results = map(&items, 4, |n| {
+            // Earlier items sleep longer, so they finish last.
+            thread::sleep(Duration::from_millis((16 - n) * 3));
+            n * 10
+        });
+        assert_eq!(results, items.iter().map(|n| n * 10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn map_handles_edge_job_counts() {
+        let items = [1, 2, 3];
+        assert_eq!(map(&items, 0, |n| n + 1), vec![2, 3, 4]);
+        assert_eq!(map(&items, 64, |n| n + 1), vec![2, 3, 4]);
+        assert!(map(&[] as &[u32], 4, |n| *n).is_empty());
+    }
+}
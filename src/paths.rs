@@ -1,3 +1,4 @@
+use std::fs;
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
@@ -5,6 +6,10 @@ use anyhow::{Context, Result};
 use crate::config;
 use crate::git;
 
+/// Directories under the clones dir starting with this prefix hold spaces'
+/// own bookkeeping (pool, staging) and are never treated as spaces.
+pub const INTERNAL_DIR_PREFIX: &str = ".spaces-";
+
 pub fn repo_root() -> Result<PathBuf> {
     let root = git::git_stdout(["rev-parse", "--show-toplevel"], None)?;
     Ok(PathBuf::from(root))
@@ -22,6 +27,52 @@ pub fn sanitize_branch_name(name: &str) -> String {
     out.trim_matches('-').to_string()
 }
 
+/// Derives a branch name from a space name, replacing characters git never
+/// allows in refs. The result is still checked with `git check-ref-format`.
+pub fn branch_from_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for ch in name.trim().chars() {
+        let replacement = match ch {
+            ' ' | '~' | '^' | ':' | '?' | '*' | '[' | '\\' => '-',
+            c if c.is_control() => '-',
+            _ => ch,
+        };
+        out.push(replacement);
+    }
+    out
+}
+
+pub fn slugify(text: &str, max_len: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if ch.is_ascii_alphanumeric() {
+            out.push(ch.to_ascii_lowercase());
+        } else if !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    let mut slug: String = out.trim_matches('-').chars().take(max_len).collect();
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
+
+pub fn git_dir(path: &Path) -> Option<PathBuf> {
+    let dot_git = path.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let content = fs::read_to_string(&dot_git).ok()?;
+    let target = content.trim().strip_prefix("gitdir:")?.trim();
+    let dir = PathBuf::from(target);
+    if dir.is_relative() {
+        Some(path.join(dir))
+    } else {
+        Some(dir)
+    }
+}
+
 pub fn expand_home(path: &str) -> PathBuf {
     if path == "~" {
         return dirs::home_dir().unwrap_or_else(|| PathBuf::from("~"));
@@ -54,7 +105,33 @@ pub fn clones_dir(repo_root: &Path) -> Result<PathBuf> {
 }
 
 pub fn clones_prefix(repo_root: &Path) -> Result<String> {
-    Ok(config::cfg_default("spaces.clones.prefix", "SPACES_CLONES_PREFIX", "", None, repo_root)?)
+    config::cfg_default("spaces.clones.prefix", "SPACES_CLONES_PREFIX", "", None, repo_root)
+}
+
+pub fn list_clone_dirs(clones_dir: &Path, prefix: &str) -> Result<Vec<PathBuf>> {
+    if !clones_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut out = Vec::new();
+    for entry in fs::read_dir(clones_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        if let Some(name) = path.file_name().and_then(|s| s.to_str())
+            && (!name.starts_with(prefix) || name.starts_with(INTERNAL_DIR_PREFIX))
+        {
+            continue;
+        }
+        out.push(path);
+    }
+    Ok(out)
+}
+
+pub fn state_file(repo_root: &Path) -> Result<PathBuf> {
+    let common = git::git_stdout(["rev-parse", "--path-format=absolute", "--git-common-dir"], Some(repo_root))?;
+    Ok(PathBuf::from(common).join("spaces-state"))
 }
 
 pub fn mirror_dir(repo_root: &Path) -> Result<PathBuf> {
@@ -85,10 +162,10 @@ pub fn default_branch(repo_root: &Path) -> Result<String> {
         return Ok(configured);
     }
 
-    if let Some(origin_head) = git::git_stdout_opt(["symbolic-ref", "--quiet", "refs/remotes/origin/HEAD"], Some(repo_root)) {
-        if let Some(stripped) = origin_head.strip_prefix("refs/remotes/origin/") {
-            return Ok(stripped.to_string());
-        }
+    if let Some(origin_head) = git::git_stdout_opt(["symbolic-ref", "--quiet", "refs/remotes/origin/HEAD"], Some(repo_root))
+        && let Some(stripped) = origin_head.strip_prefix("refs/remotes/origin/")
+    {
+        return Ok(stripped.to_string());
     }
 
     if git::git_check(["show-ref", "--verify", "--quiet", "refs/remotes/origin/main"], Some(repo_root)).is_ok() {
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::paths;
+
+const BUILDING_PREFIX: &str = "building-";
+
+pub fn pool_dir(clones_dir: &Path) -> PathBuf {
+    clones_dir.join(format!("{}pool", paths::INTERNAL_DIR_PREFIX))
+}
+
+/// Returns the pool entries that finished building, oldest first.
+pub fn ready_entries(clones_dir: &Path) -> Result<Vec<PathBuf>> {
+    let dir = pool_dir(clones_dir);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut out = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(BUILDING_PREFIX) {
+            continue;
+        }
+        out.push(entry.path());
+    }
+    out.sort();
+    Ok(out)
+}
+
+/// Returns a unique staging path for a new pool entry and the path it is
+/// published under once it is ready.
+pub fn entry_paths(clones_dir: &Path, index: usize) -> (PathBuf, PathBuf) {
+    let dir = pool_dir(clones_dir);
+    let stamp = format!("{:012}-{}-{index}", crate::meta::now(), std::process::id());
+    (dir.join(format!("{BUILDING_PREFIX}{stamp}")), dir.join(stamp))
+}
+
+/// The staging path a ready entry was built at, where its copy step and
+/// `postCreate` hooks ran.
+pub fn built_at(ready: &Path) -> PathBuf {
+    let name = ready.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    ready.with_file_name(format!("{BUILDING_PREFIX}{name}"))
+}
+
+pub fn publish(building: &Path, ready: &Path) -> Result<()> {
+    fs::rename(building, ready).with_context(|| format!("publish pool entry {}", ready.display()))
+}
+
+/// Moves a ready pool entry to `dest` and returns the entry's pool path.
+/// Renaming is atomic, so concurrent `spaces new` runs never claim the same
+/// entry.
+pub fn claim(clones_dir: &Path, dest: &Path) -> Result<Option<PathBuf>> {
+    if dest.exists() {
+        return Ok(None);
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    for entry in ready_entries(clones_dir)? {
+        if fs::rename(&entry, dest).is_ok() {
+            return Ok(Some(entry));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::config::{self, Scope};
+
+/// A named bundle of `new` settings read from `spaces.profile.<name>.*`.
+pub struct Profile {
+    pub name: String,
+    pub branch_prefix: Option<String>,
+    pub base_ref: Option<String>,
+    pub copy_include: Vec<String>,
+    pub copy_exclude: Vec<String>,
+    pub copy_include_dirs: Vec<String>,
+    pub copy_exclude_dirs: Vec<String>,
+    pub no_fetch: bool,
+    pub no_copy: bool,
+}
+
+pub fn key(profile: &str, suffix: &str) -> String {
+    format!("spaces.profile.{profile}.{suffix}")
+}
+
+pub fn load(name: &str, repo_root: &Path) -> Result<Profile> {
+    let prefix = key(name, "");
+    let lines = config::cfg_list(Scope::Auto, repo_root)?;
+    if !lines.iter().any(|line| line.starts_with(&prefix)) {
+        bail!("Unknown profile: {name} (no {prefix}* keys configured)");
+    }
+
+    let get = |suffix: &str| -> Result<Option<String>> {
+        let value = config::cfg_default(&key(name, suffix), "", "", None, repo_root)?;
+        Ok(if value.is_empty() { None } else { Some(value) })
+    };
+    let get_all = |suffix: &str| config::cfg_get_all(&key(name, suffix), Scope::Auto, repo_root);
+
+    Ok(Profile {
+        name: name.to_string(),
+        branch_prefix: get("branchPrefix")?,
+        base_ref: get("baseRef")?,
+        copy_include: get_all("copy.include"),
+        copy_exclude: get_all("copy.exclude"),
+        copy_include_dirs: get_all("copy.includeDirs"),
+        copy_exclude_dirs: get_all("copy.excludeDirs"),
+        no_fetch: get("noFetch")?.is_some_and(|v| config::is_truthy(&v)),
+        no_copy: get("noCopy")?.is_some_and(|v| config::is_truthy(&v)),
+    })
+}
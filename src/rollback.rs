@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{bail, Result};
+
+use crate::clone;
+use crate::git;
+use crate::ui;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Turns Ctrl-C into an error at the next step boundary so a half-built
+/// space can be rolled back instead of the process dying mid-way.
+pub fn install_interrupt_handler() {
+    let _ = ctrlc::set_handler(|| {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        ui::log_warn("Interrupted; stopping after the current step...");
+    });
+}
+
+pub fn check_interrupted() -> Result<()> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        bail!("Interrupted");
+    }
+    Ok(())
+}
+
+/// Removes a space that is still being created when dropped, unless
+/// `commit` was called or the user asked to keep failed spaces.
+pub struct SpaceGuard {
+    path: PathBuf,
+    mirror_dir: PathBuf,
+    keep: bool,
+    committed: bool,
+}
+
+impl SpaceGuard {
+    pub fn new(path: &Path, mirror_dir: &Path, keep: bool) -> SpaceGuard {
+        SpaceGuard {
+            path: path.to_path_buf(),
+            mirror_dir: mirror_dir.to_path_buf(),
+            keep,
+            committed: false,
+        }
+    }
+
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for SpaceGuard {
+    fn drop(&mut self) {
+        if self.committed || !self.path.exists() {
+            return;
+        }
+        if self.keep {
+            ui::log_warn(&format!("Keeping failed space for debugging: {}", self.path.display()));
+            return;
+        }
+
+        ui::log_step(&format!("Rolling back: {}", self.path.display()));
+        let worktree = clone::Backend::of_space(&self.path) == clone::Backend::Worktree;
+        if worktree && clone::remove_worktree(&self.path, true).is_ok() {
+            return;
+        }
+        if let Err(err) = std::fs::remove_dir_all(&self.path) {
+            ui::log_error(&format!("Rollback failed: {err}"));
+        }
+        if worktree {
+            let _ = git::git_check(["worktree", "prune"], Some(&self.mirror_dir));
+        }
+    }
+}
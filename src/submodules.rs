@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::git;
+use crate::paths;
+use crate::ui;
+
+pub struct Submodule {
+    pub name: String,
+    pub path: String,
+    pub url: String,
+}
+
+pub fn mirrors_root(mirror_dir: &Path) -> PathBuf {
+    let mut root = mirror_dir.as_os_str().to_owned();
+    root.push("-submodules");
+    PathBuf::from(root)
+}
+
+pub fn mirror_for(mirror_dir: &Path, name: &str) -> PathBuf {
+    mirrors_root(mirror_dir).join(paths::sanitize_branch_name(name))
+}
+
+/// Lists the submodules registered in a space. Urls come from the space
+/// config, so `git submodule init` must have resolved relative urls first.
+pub fn list(space: &Path) -> Vec<Submodule> {
+    let Some(output) = git::git_stdout_opt(["config", "--get-regexp", "^submodule\\..*\\.url$"], Some(space)) else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    for line in output.lines() {
+        let Some((key, url)) = line.split_once(' ') else {
+            continue;
+        };
+        let Some(name) = key.strip_prefix("submodule.").and_then(|k| k.strip_suffix(".url")) else {
+            continue;
+        };
+        let path_key = format!("submodule.{name}.path");
+        let path = git::git_stdout_opt(["config", "-f", ".gitmodules", "--get", &path_key], Some(space))
+            .unwrap_or_else(|| name.to_string());
+        out.push(Submodule {
+            name: name.to_string(),
+            path,
+            url: url.to_string(),
+        });
+    }
+    out
+}
+
+/// Initializes the submodules of a space, borrowing objects from a mirror
+/// per submodule so only missing objects hit the network.
+pub fn init(space: &Path, mirror_dir: &Path, fetch: bool, dissociate: bool) -> Result<()> {
+    if !space.join(".gitmodules").exists() {
+        return Ok(());
+    }
+
+    ui::log_step("Initializing submodules...");
+    git::git_check(["submodule", "init"], Some(space))?;
+
+    for submodule in list(space) {
+        let mirror = mirror_for(mirror_dir, &submodule.name);
+        let mirrored = if mirror.exists() {
+            if fetch {
+                let _ = git::git_check(["fetch", "--prune", "origin"], Some(&mirror));
+            }
+            true
+        } else {
+            ensure_mirror(&submodule.url, &mirror).is_ok()
+        };
+
+        let mut args = vec!["submodule".to_string(), "update".to_string()];
+        if mirrored {
+            args.push("--reference".to_string());
+            args.push(mirror.to_string_lossy().to_string());
+            if dissociate {
+                args.push("--dissociate".to_string());
+            }
+        } else {
+            ui::log_warn(&format!("No mirror for submodule {}; cloning without reference", submodule.name));
+        }
+        args.push("--".to_string());
+        args.push(submodule.path.clone());
+        git::git_check(&args, Some(space)).with_context(|| format!("update submodule {}", submodule.path))?;
+        ui::log_info(&format!("Submodule {}", submodule.path));
+    }
+    Ok(())
+}
+
+fn ensure_mirror(url: &str, mirror: &Path) -> Result<()> {
+    if let Some(parent) = mirror.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("create submodule mirror parent {parent:?}"))?;
+    }
+    ui::log_step(&format!("Creating submodule mirror: {}", mirror.display()));
+    git::git_check(["clone", "--mirror", url, mirror.to_string_lossy().as_ref()], None)
+}
+
+pub fn update_mirrors(mirror_dir: &Path) -> Result<Vec<PathBuf>> {
+    let root = mirrors_root(mirror_dir);
+    if !root.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut updated = Vec::new();
+    for entry in fs::read_dir(&root)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if git::git_check(["fetch", "--prune", "origin"], Some(&path)).is_ok() {
+            updated.push(path);
+        } else {
+            ui::log_warn(&format!("Failed to update submodule mirror: {}", path.display()));
+        }
+    }
+    updated.sort();
+    Ok(updated)
+}
@@ -1,8 +1,11 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Result};
+use serde::Serialize;
 
+use crate::clone::Backend;
 use crate::git;
+use crate::meta::{self, SpaceMeta};
 use crate::paths;
 
 pub struct Target {
@@ -10,6 +13,10 @@ pub struct Target {
     pub path: PathBuf,
     pub name: String,
     pub branch: String,
+    pub meta: Option<SpaceMeta>,
+    /// Found by prefix, checked-out branch or fuzzy match rather than by an
+    /// exact id, name or folder.
+    pub inexact: bool,
 }
 
 pub fn current_branch(path: &Path) -> Option<String> {
@@ -22,48 +29,313 @@ pub fn current_branch(path: &Path) -> Option<String> {
     }
 }
 
+/// Reads the checked-out branch straight from the HEAD file, without
+/// running git. Returns `None` when detached or unreadable.
+pub fn head_branch(path: &Path) -> Option<String> {
+    let head = std::fs::read_to_string(paths::git_dir(path)?.join("HEAD")).ok()?;
+    head.trim().strip_prefix("ref: refs/heads/").map(str::to_string)
+}
+
+/// Resolves `main` to the main repo, then a space by exact name or folder,
+/// then id (`3` or `#3`; `1` is the main repo), then unique prefix,
+/// checked-out branch and fuzzy match. `#N` only ever means an id.
 pub fn resolve_target(identifier: &str, repo_root: &Path, clones_dir: &Path, prefix: &str) -> Result<Target> {
-    if identifier == "1" {
-        let branch = current_branch(repo_root).unwrap_or_else(|| "(detached)".to_string());
-        return Ok(Target {
-            is_main: true,
-            path: repo_root.to_path_buf(),
-            name: "main".to_string(),
-            branch,
-        });
+    if identifier == "main" {
+        return Ok(main_target(repo_root));
     }
-
+    let forced_id = identifier.starts_with('#');
+    let id = identifier.strip_prefix('#').unwrap_or(identifier).parse::<u32>().ok();
     let sanitized = paths::sanitize_branch_name(identifier);
     let direct = clones_dir.join(format!("{prefix}{sanitized}"));
-    if direct.is_dir() {
-        let branch = current_branch(&direct).unwrap_or_else(|| "(detached)".to_string());
-        return Ok(Target {
-            is_main: false,
-            name: identifier.to_string(),
-            path: direct,
-            branch,
-        });
+    if !forced_id && direct.is_dir() {
+        return Ok(space_target(direct, prefix));
+    }
+
+    let candidates: Vec<Candidate> = paths::list_clone_dirs(clones_dir, prefix)?
+        .into_iter()
+        .map(|path| {
+            let folder = space_name(&path, prefix);
+            let name = meta::read(&path).map(|m| m.name).unwrap_or_else(|| folder.clone());
+            Candidate { path, name, folder }
+        })
+        .collect();
+
+    if !forced_id && let Some(found) = candidates.iter().find(|c| c.name == identifier) {
+        return Ok(space_target(found.path.clone(), prefix));
+    }
+
+    match id {
+        Some(1) => return Ok(main_target(repo_root)),
+        Some(id) => {
+            meta::backfill_ids(repo_root, clones_dir, prefix)?;
+            if let Some(path) = meta::find_by_id(clones_dir, prefix, id) {
+                return Ok(space_target(path, prefix));
+            }
+            if forced_id {
+                bail!("No space with id {id}");
+            }
+        }
+        None if forced_id => bail!("Invalid space id: {identifier}"),
+        None => {}
+    }
+
+    let by_prefix: Vec<&Candidate> = candidates
+        .iter()
+        .filter(|c| c.name.starts_with(identifier) || c.folder.starts_with(&sanitized))
+        .collect();
+    if let Some(found) = pick_unique(identifier, "prefix", &by_prefix)? {
+        return Ok(inexact(space_target(found.path.clone(), prefix)));
+    }
+
+    let by_branch: Vec<&Candidate> = candidates
+        .iter()
+        .filter(|c| current_branch(&c.path).as_deref() == Some(identifier))
+        .collect();
+    if let Some(found) = pick_unique(identifier, "branch", &by_branch)? {
+        return Ok(inexact(space_target(found.path.clone(), prefix)));
+    }
+
+    let by_fuzzy: Vec<&Candidate> = candidates
+        .iter()
+        .filter(|c| is_subsequence(identifier, &c.name) || is_subsequence(&sanitized, &c.folder))
+        .collect();
+    if let Some(found) = pick_unique(identifier, "fuzzy", &by_fuzzy)? {
+        return Ok(inexact(space_target(found.path.clone(), prefix)));
     }
 
     bail!("Target not found for space: {identifier}")
 }
 
-pub fn status(path: &Path) -> String {
-    if !path.exists() {
-        return "missing".to_string();
+struct Candidate {
+    path: PathBuf,
+    name: String,
+    folder: String,
+}
+
+fn pick_unique<'a>(identifier: &str, kind: &str, matches: &[&'a Candidate]) -> Result<Option<&'a Candidate>> {
+    match matches {
+        [] => Ok(None),
+        [only] => Ok(Some(only)),
+        _ => {
+            let mut names: Vec<&str> = matches.iter().map(|c| c.name.as_str()).collect();
+            names.sort();
+            bail!(
+                "Ambiguous space '{identifier}' ({kind} match); candidates:\n  {}",
+                names.join("\n  ")
+            )
+        }
     }
-    let branch = current_branch(path).unwrap_or_else(|| "(detached)".to_string());
-    if branch == "(detached)" {
-        return "detached".to_string();
+}
+
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut chars = haystack.chars();
+    needle.chars().all(|n| chars.any(|h| h.eq_ignore_ascii_case(&n)))
+}
+
+fn main_target(repo_root: &Path) -> Target {
+    Target {
+        is_main: true,
+        path: repo_root.to_path_buf(),
+        name: "main".to_string(),
+        branch: current_branch(repo_root).unwrap_or_else(|| "(detached)".to_string()),
+        meta: None,
+        inexact: false,
+    }
+}
+
+fn space_target(path: PathBuf, prefix: &str) -> Target {
+    let branch = current_branch(&path).unwrap_or_else(|| "(detached)".to_string());
+    let meta = meta::read(&path);
+    let name = match &meta {
+        Some(meta) => meta.name.clone(),
+        None => space_name(&path, prefix),
+    };
+    Target {
+        is_main: false,
+        path,
+        name,
+        branch,
+        meta,
+        inexact: false,
+    }
+}
+
+fn inexact(target: Target) -> Target {
+    Target { inexact: true, ..target }
+}
+
+/// Working tree and sync state of a checkout.
+pub struct Status {
+    /// `ok`, `dirty`, `detached` or `missing`.
+    pub state: &'static str,
+    pub branch: Option<String>,
+    pub upstream: Option<String>,
+    /// Commits ahead of / behind the upstream; `None` without one.
+    pub ahead: Option<u32>,
+    pub behind: Option<u32>,
+    /// Commits on HEAD that are not on the base ref the space started from.
+    pub base_ahead: Option<u32>,
+    /// Whether a remote-tracking ref exists for the branch. `None` when
+    /// detached or for worktree spaces, whose mirror has no such refs.
+    pub pushed: Option<bool>,
+    pub changes: Changes,
+}
+
+#[derive(Serialize, Default)]
+pub struct Changes {
+    pub staged: u32,
+    pub unstaged: u32,
+    pub untracked: u32,
+    pub conflicted: u32,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.staged + self.unstaged + self.untracked + self.conflicted == 0
+    }
+}
+
+pub fn status(path: &Path, base_ref: Option<&str>) -> Status {
+    let mut status = Status {
+        state: "missing",
+        branch: None,
+        upstream: None,
+        ahead: None,
+        behind: None,
+        base_ahead: None,
+        pushed: None,
+        changes: Changes::default(),
+    };
+    if !path.exists() {
+        return status;
     }
 
-    if let Some(output) = git::git_stdout_opt(["status", "--porcelain"], Some(path)) {
-        if !output.trim().is_empty() {
-            return "dirty".to_string();
+    match git::git_output(["status", "--porcelain=v2", "--branch", "-z"], Some(path)) {
+        Ok(output) if output.status.success() => {
+            parse_porcelain_v2(&String::from_utf8_lossy(&output.stdout), &mut status)
         }
+        _ => status.branch = current_branch(path),
+    }
+
+    status.state = if status.branch.is_none() {
+        "detached"
+    } else if !status.changes.is_empty() {
+        "dirty"
+    } else {
+        "ok"
+    };
+
+    if let Some(branch) = &status.branch
+        && Backend::of_space(path) == Backend::Clone
+    {
+        let pattern = format!("refs/remotes/*/{branch}");
+        let remote = git::git_stdout_opt(["for-each-ref", "--count=1", "--format=%(refname)", &pattern], Some(path));
+        status.pushed = Some(remote.is_some());
+    }
+
+    if let Some(base) = base_ref.filter(|b| !b.is_empty()) {
+        // Prefer the remote-tracking ref so a stale local branch does not
+        // inflate the count.
+        let remote = format!("refs/remotes/origin/{base}");
+        let base = if git::git_check(["rev-parse", "--verify", "--quiet", &remote], Some(path)).is_ok() {
+            remote
+        } else {
+            base.to_string()
+        };
+        status.base_ahead = git::git_stdout_opt(["rev-list", "--count", &format!("{base}..HEAD")], Some(path))
+            .and_then(|count| count.parse().ok());
+    }
+    status
+}
+
+pub fn stash_count(path: &Path) -> u32 {
+    git::git_stdout_opt(["rev-list", "--walk-reflogs", "--count", "refs/stash"], Some(path))
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Names the operation a checkout is in the middle of, from the marker
+/// files git leaves in its (per-worktree) git dir.
+pub fn operation_in_progress(path: &Path) -> Option<&'static str> {
+    let git_dir = paths::git_dir(path)?;
+    let markers = [
+        ("rebase-merge", "rebase"),
+        ("rebase-apply", "rebase"),
+        ("MERGE_HEAD", "merge"),
+        ("CHERRY_PICK_HEAD", "cherry-pick"),
+        ("REVERT_HEAD", "revert"),
+        ("BISECT_LOG", "bisect"),
+    ];
+    markers
+        .iter()
+        .find(|(marker, _)| git_dir.join(marker).exists())
+        .map(|(_, operation)| *operation)
+}
+
+/// Commits on HEAD that no remote has yet, as `<short sha> <subject>`,
+/// newest first and at most `limit`, plus the total count. Uses the upstream
+/// when there is one and every remote-tracking ref otherwise; worktree
+/// spaces without an upstream have nothing to compare against.
+pub fn unpushed_commits(path: &Path, status: &Status, limit: usize) -> (u32, Vec<String>) {
+    let range: Vec<&str> = if status.ahead.is_some() {
+        vec!["@{upstream}..HEAD"]
+    } else if Backend::of_space(path) == Backend::Clone {
+        vec!["HEAD", "--not", "--remotes"]
+    } else {
+        return (0, Vec::new());
+    };
+
+    let mut count_args = vec!["rev-list", "--count"];
+    count_args.extend(&range);
+    let count = git::git_stdout_opt(&count_args, Some(path))
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0);
+    if count == 0 {
+        return (0, Vec::new());
     }
 
-    "ok".to_string()
+    let limit = limit.to_string();
+    let mut log_args = vec!["log", "--format=%h %s", "-n", &limit];
+    log_args.extend(&range);
+    let commits = git::git_stdout_opt(&log_args, Some(path))
+        .map(|out| out.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+    (count, commits)
+}
+
+fn parse_porcelain_v2(text: &str, status: &mut Status) {
+    let mut records = text.split('\0').filter(|r| !r.is_empty());
+    while let Some(record) = records.next() {
+        if let Some(head) = record.strip_prefix("# branch.head ") {
+            if head != "(detached)" {
+                status.branch = Some(head.to_string());
+            }
+        } else if let Some(upstream) = record.strip_prefix("# branch.upstream ") {
+            status.upstream = Some(upstream.to_string());
+        } else if let Some(ab) = record.strip_prefix("# branch.ab ") {
+            let mut parts = ab.split(' ');
+            status.ahead = parts.next().and_then(|a| a.trim_start_matches('+').parse().ok());
+            status.behind = parts.next().and_then(|b| b.trim_start_matches('-').parse().ok());
+        } else if record.starts_with("1 ") || record.starts_with("2 ") {
+            let xy = record.as_bytes();
+            if xy.len() > 3 {
+                if xy[2] != b'.' {
+                    status.changes.staged += 1;
+                }
+                if xy[3] != b'.' {
+                    status.changes.unstaged += 1;
+                }
+            }
+            // Renames carry the original path as an extra record.
+            if record.starts_with("2 ") {
+                records.next();
+            }
+        } else if record.starts_with("u ") {
+            status.changes.conflicted += 1;
+        } else if record.starts_with("? ") {
+            status.changes.untracked += 1;
+        }
+    }
 }
 
 pub fn space_name(path: &Path, prefix: &str) -> String {
@@ -73,3 +345,160 @@ pub fn space_name(path: &Path, prefix: &str) -> String {
         .unwrap_or("space");
     name.strip_prefix(prefix).unwrap_or(name).to_string()
 }
+
+pub fn display_name(path: &Path, prefix: &str) -> String {
+    match meta::read(path) {
+        Some(meta) => meta.name,
+        None => space_name(path, prefix),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{self, TempDir};
+
+    fn parse(text: &str) -> Status {
+        let mut status = status(Path::new("/nonexistent/spaces-test"), None);
+        parse_porcelain_v2(text, &mut status);
+        status
+    }
+
+    #[test]
+    fn porcelain_v2_reads_branch_headers() {
+        let status = parse(
+            "# branch.oid 1234\0# branch.head feature\0# branch.upstream origin/feature\0# branch.ab +3 -2\0",
+        );
+        assert_eq!(status.branch.as_deref(), Some("feature"));
+        assert_eq!(status.upstream.as_deref(), Some("origin/feature"));
+        assert_eq!(status.ahead, Some(3));
+        assert_eq!(status.behind, Some(2));
+        assert!(status.changes.is_empty());
+    }
+
+    #[test]
+    fn porcelain_v2_detached_head_has_no_branch() {
+        let status = parse("# branch.oid 1234\0# branch.head (detached)\0");
+        assert_eq!(status.branch, None);
+        assert_eq!(status.ahead, None);
+    }
+
+    #[test]
+    fn porcelain_v2_counts_changes() {
+        let text = [
+            "# branch.head main",
+            "1 M. N... 100644 100644 100644 aaa bbb staged.txt",
+            "1 .M N... 100644 100644 100644 aaa bbb unstaged.txt",
+            "1 MM N... 100644 100644 100644 aaa bbb both.txt",
+            // A rename is followed by its original path, which must not be
+            // read as a record of its own.
+            "2 R. N... 100644 100644 100644 aaa bbb R100 new.txt",
+            "? looks-like-a-record.txt",
+            "u UU N... 100644 100644 100644 100644 aaa bbb ccc conflict.txt",
+            "? untracked.txt",
+        ]
+        .join("\0");
+        let status = parse(&text);
+        assert_eq!(status.changes.staged, 3);
+        assert_eq!(status.changes.unstaged, 2);
+        assert_eq!(status.changes.conflicted, 1);
+        assert_eq!(status.changes.untracked, 1);
+    }
+
+    #[test]
+    fn unpushed_commits_against_upstream() {
+        let tmp = TempDir::new("unpushed-upstream");
+        let origin = tmp.path().join("origin");
+        testutil::init_repo(&origin);
+        testutil::git(tmp.path(), &["clone", "-q", "origin", "space"]);
+        let space = tmp.path().join("space");
+        testutil::commit(&space, "first");
+        testutil::commit(&space, "second");
+
+        let status = status(&space, None);
+        assert_eq!(status.ahead, Some(2));
+        let (count, commits) = unpushed_commits(&space, &status, 1);
+        assert_eq!(count, 2);
+        assert_eq!(commits.len(), 1);
+        assert!(commits[0].ends_with(" second"), "{commits:?}");
+    }
+
+    #[test]
+    fn unpushed_commits_without_upstream_uses_remotes() {
+        let tmp = TempDir::new("unpushed-remotes");
+        let origin = tmp.path().join("origin");
+        testutil::init_repo(&origin);
+        testutil::git(tmp.path(), &["clone", "-q", "origin", "space"]);
+        let space = tmp.path().join("space");
+        testutil::git(&space, &["checkout", "-q", "--no-track", "-b", "topic"]);
+
+        let status = status(&space, None);
+        assert_eq!(status.ahead, None);
+        assert_eq!(unpushed_commits(&space, &status, 10).0, 0);
+
+        testutil::commit(&space, "local");
+        let (count, commits) = unpushed_commits(&space, &status, 10);
+        assert_eq!(count, 1);
+        assert!(commits[0].ends_with(" local"), "{commits:?}");
+    }
+
+    #[test]
+    fn subsequence_is_ordered_and_case_insensitive() {
+        assert!(is_subsequence("flog", "feature-login"));
+        assert!(is_subsequence("FL", "feature-login"));
+        assert!(is_subsequence("", "anything"));
+        assert!(!is_subsequence("golf", "feature-login"));
+        assert!(!is_subsequence("loginx", "feature-login"));
+    }
+
+    fn spaces_dir(folders: &[&str]) -> TempDir {
+        let tmp = TempDir::new("resolve");
+        for folder in folders {
+            std::fs::create_dir_all(tmp.path().join("clones").join(folder)).unwrap();
+        }
+        tmp
+    }
+
+    #[test]
+    fn fuzzy_match_reports_every_candidate_when_ambiguous() {
+        let tmp = spaces_dir(&["s-feature-login", "s-feature-logout", "s-bugfix"]);
+        let clones = tmp.path().join("clones");
+        let err = resolve_target("ftlo", tmp.path(), &clones, "s-").err().expect("ambiguous");
+        let message = err.to_string();
+        assert!(message.contains("fuzzy match"), "{message}");
+        assert!(message.contains("feature-login\n  feature-logout"), "{message}");
+        assert!(!message.contains("bugfix"), "{message}");
+    }
+
+    #[test]
+    fn unique_fuzzy_match_is_inexact() {
+        let tmp = spaces_dir(&["s-feature-login", "s-bugfix"]);
+        let clones = tmp.path().join("clones");
+        let target = resolve_target("ftlo", tmp.path(), &clones, "s-").unwrap();
+        assert_eq!(target.name, "feature-login");
+        assert!(target.inexact);
+
+        let target = resolve_target("bugfix", tmp.path(), &clones, "s-").unwrap();
+        assert!(!target.inexact);
+    }
+
+    #[test]
+    fn main_names_the_main_repo_before_any_space() {
+        let tmp = spaces_dir(&["s-main", "s-other"]);
+        let clones = tmp.path().join("clones");
+        let target = resolve_target("main", tmp.path(), &clones, "s-").unwrap();
+        assert!(target.is_main);
+        assert_eq!(target.path, tmp.path());
+    }
+
+    #[test]
+    fn branch_match_is_inexact() {
+        let tmp = TempDir::new("resolve-branch");
+        let clones = tmp.path().join("clones");
+        testutil::init_repo(&clones.join("s-login"));
+        testutil::git(&clones.join("s-login"), &["switch", "-q", "-c", "topic"]);
+        let target = resolve_target("topic", tmp.path(), &clones, "s-").unwrap();
+        assert_eq!(target.name, "login");
+        assert!(target.inexact);
+    }
+}
@@ -0,0 +1,68 @@
+//! Scratch directories and git repositories for unit tests.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// A fresh directory under the system temp dir, removed on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(label: &str) -> TempDir {
+        let index = NEXT.fetch_add(1, Ordering::SeqCst);
+        let path = std::env::temp_dir().join(format!("spaces-test-{label}-{}-{index}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("create temp dir");
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs git in `dir` with a fixed identity and returns trimmed stdout.
+/// Panics when git fails.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(["-c", "user.name=spaces", "-c", "user.email=spaces@example.com"])
+        .args(["-c", "init.defaultBranch=main", "-c", "commit.gpgSign=false"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("run git");
+    assert!(
+        output.status.success(),
+        "git {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Creates a repository at `path` on `main` with one commit.
+pub fn init_repo(path: &Path) {
+    std::fs::create_dir_all(path).expect("create repo dir");
+    git(path, &["init", "-q", "-b", "main"]);
+    commit(path, "init");
+}
+
+/// Commits a change to `file.txt` and returns the new HEAD.
+pub fn commit(repo: &Path, message: &str) -> String {
+    let file = repo.join("file.txt");
+    let mut content = std::fs::read_to_string(&file).unwrap_or_default();
+    content.push_str(message);
+    content.push('\n');
+    std::fs::write(&file, content).expect("write file.txt");
+    git(repo, &["add", "file.txt"]);
+    git(repo, &["commit", "-q", "-m", message]);
+    git(repo, &["rev-parse", "HEAD"])
+}